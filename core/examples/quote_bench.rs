@@ -0,0 +1,79 @@
+//! Times `compute_dry` against a linear walk of the book on update-then-quote cycles:
+//! every book update is followed by the quotes an order form makes before the next one.
+//!
+//!     cargo run --release -p core --example quote_bench
+use core::orderbook::OrderBook;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::time::{Duration, Instant};
+
+const CYCLES: usize = 20_000;
+const LEVELS: i64 = 2_000;
+
+// Small deterministic generator so every run replays the same feed
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+// 0.5 ticks from 30000, 0.01 to 0.1 of base a level
+fn setup_order_book(rng: &mut Lcg) -> OrderBook {
+    let level = |rng: &mut Lcg, ticks: i64| (Decimal::new(60_000 + ticks, 1) / dec!(2), Decimal::new(1 + rng.next(10) as i64, 2));
+    let asks = (1..=LEVELS).map(|ticks| level(rng, ticks)).collect();
+    let bids = (1..=LEVELS).map(|ticks| level(rng, -ticks)).collect();
+    let mut order_book = OrderBook::new();
+    order_book.initialize(asks, bids, None);
+    order_book
+}
+
+// One level update, half of them within the top 10 levels of a side
+fn random_update(rng: &mut Lcg) -> (bool, Vec<(Decimal, Decimal)>) {
+    let is_ask = rng.next(2) == 0;
+    let depth = if rng.next(2) == 0 { 1 + rng.next(10) } else { 1 + rng.next(LEVELS as u64) } as i64;
+    let ticks = if is_ask { depth } else { -depth };
+    (is_ask, vec![(Decimal::new(60_000 + ticks, 1) / dec!(2), Decimal::new(1 + rng.next(10) as i64, 2))])
+}
+
+// A 1k / 10k / 100k notional slippage table and the order form amount typed digit by digit
+const QUOTES: [Decimal; 8] = [
+    dec!(1000), dec!(10000), dec!(100000),
+    dec!(2), dec!(25), dec!(250), dec!(2500), dec!(25000),
+];
+
+fn linear_dry(order_book: &OrderBook, fill_amount: Decimal, is_buy: bool) -> (Decimal, Decimal, Decimal) {
+    match order_book.compute_fill(fill_amount, true, is_buy) {
+        Ok(estimate) if estimate.is_complete => (estimate.avg_price, estimate.filled_base, estimate.slippage),
+        _ => (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO),
+    }
+}
+
+fn run(quote: impl Fn(&OrderBook, Decimal, bool) -> (Decimal, Decimal, Decimal)) -> (Duration, Decimal) {
+    let mut rng = Lcg(7);
+    let mut order_book = setup_order_book(&mut rng);
+    let mut elapsed = Duration::ZERO;
+    let mut checksum = Decimal::ZERO;
+    for cycle in 0..CYCLES {
+        let (is_ask, updates) = random_update(&mut rng);
+        let start = Instant::now();
+        order_book.update_order(is_ask, updates).unwrap();
+        for fill_amount in QUOTES {
+            checksum += quote(&order_book, fill_amount, cycle % 2 == 0).0;
+        }
+        elapsed += start.elapsed();
+    }
+    (elapsed, checksum)
+}
+
+fn main() {
+    let (linear, linear_checksum) = run(linear_dry);
+    let (cached, cached_checksum) = run(|order_book, fill_amount, is_buy| order_book.compute_dry(fill_amount, true, is_buy).unwrap_or_default());
+    assert_eq!(linear_checksum, cached_checksum, "cached quotes differ from the linear walk");
+
+    println!("{} cycles of 1 update and {} quotes on {} levels a side", CYCLES, QUOTES.len(), LEVELS);
+    println!("linear walk   {:>10.2?}", linear);
+    println!("compute_dry   {:>10.2?}  ({:.1}x)", cached, linear.as_secs_f64() / cached.as_secs_f64());
+}
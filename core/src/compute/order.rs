@@ -2,13 +2,13 @@ use core::fmt;
 use std::collections::HashMap;
 use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal_macros::dec;
-use crate::{orderbook::OrderBook, log};
+use crate::{error::{CoreError, CoreResult}, orderbook::{FeeInclusiveFill, FillEstimate, OrderBook, PriceCap, Side}, log};
 use serde::{Serialize, Deserialize};
 use crate::clg;
 
 /// Calculate max quantity, min quantity, entry_price, liquidation_price, fees and slippage for a given order
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
 pub enum OrderType {
     Limit,
     Market,
@@ -23,6 +23,57 @@ impl fmt::Debug for OrderType {
     }
 }
 
+/// Size of an order to open, in one of the units a trader can type it in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum QuantitySpec {
+    /// Position size in base
+    Base(Decimal),
+    /// Position notional in quote
+    Quote(Decimal),
+    /// Margin to pay in quote, the notional is `pay_amount * leverage`
+    PayAmount(Decimal),
+    /// Share of the balance used as margin, 0.25 = 25%, the notional is `balance * leverage * share`
+    PercentOfBalance(Decimal),
+}
+
+impl QuantitySpec {
+    /// The amount as typed, in the unit of the variant
+    pub fn amount(&self) -> Decimal {
+        match *self {
+            QuantitySpec::Base(amount)
+            | QuantitySpec::Quote(amount)
+            | QuantitySpec::PayAmount(amount)
+            | QuantitySpec::PercentOfBalance(amount) => amount,
+        }
+    }
+}
+
+/// Order to price with `FuturesOrderCalculation::open_order`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenOrderRequest {
+    /// `Side::Bid` buys (long), `Side::Ask` sells (short)
+    pub side: Side,
+    pub quantity: QuantitySpec,
+    pub order_type: OrderType,
+    /// Required by limit orders, ignored by market orders
+    #[serde(default)]
+    pub limit_price: Option<Decimal>,
+}
+
+impl OpenOrderRequest {
+    pub fn market(side: Side, quantity: QuantitySpec) -> Self {
+        Self { side, quantity, order_type: OrderType::Market, limit_price: None }
+    }
+
+    pub fn limit(side: Side, quantity: QuantitySpec, limit_price: Decimal) -> Self {
+        Self { side, quantity, order_type: OrderType::Limit, limit_price: Some(limit_price) }
+    }
+
+    pub fn is_buy(&self) -> bool {
+        !self.side.is_ask()
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FuturesOrder {
@@ -96,8 +147,11 @@ pub struct FuturesOrderCalculation {
     pub base_token_precision: u32,
 }
 
-pub fn string_to_decimal(s: &str, expect_msg: &str) -> Decimal {
-    Decimal::from_str_exact(s).expect(expect_msg)
+/// Most decimals a `Decimal` can hold
+const MAX_PRECISION: u32 = 28;
+
+pub fn string_to_decimal(s: &str, error_msg: &str) -> CoreResult<Decimal> {
+    Decimal::from_str_exact(s).map_err(|e| CoreError::InvalidInput(format!("{} {:?}: {}", error_msg, s, e)))
 }
 
 impl FuturesOrderCalculation {
@@ -111,21 +165,27 @@ impl FuturesOrderCalculation {
         taker_fee: String,
         maker_fee: String,
         base_token_precision: u32,
-    ) -> Self {
-        Self {
-            leverage: string_to_decimal(&leverage, "Invalid leverage"),
+    ) -> CoreResult<Self> {
+        if base_token_precision > MAX_PRECISION {
+            return Err(CoreError::InvalidPrecision(format!(
+                "Base token precision {} exceeds {} decimals",
+                base_token_precision, MAX_PRECISION
+            )));
+        }
+        Ok(Self {
+            leverage: string_to_decimal(&leverage, "Invalid leverage")?,
             collateral_long_token,
             collateral_short_token,
-            max_notional: string_to_decimal(&max_notional, "Invalid max notional"),
-            min_quantity_base: string_to_decimal(&min_quantity_base, "Invalid min quantity base"),
-            margin_ratio: string_to_decimal(&margin_ratio, "Invalid margin ratio"),
-            taker_fee: string_to_decimal(&taker_fee, "Invalid taker fee"),
-            maker_fee: string_to_decimal(&maker_fee, "Invalid maker fee"),
+            max_notional: string_to_decimal(&max_notional, "Invalid max notional")?,
+            min_quantity_base: string_to_decimal(&min_quantity_base, "Invalid min quantity base")?,
+            margin_ratio: string_to_decimal(&margin_ratio, "Invalid margin ratio")?,
+            taker_fee: string_to_decimal(&taker_fee, "Invalid taker fee")?,
+            maker_fee: string_to_decimal(&maker_fee, "Invalid maker fee")?,
             base_token_precision,
-        }
+        })
     }
 
-    /// Compute open order details
+    /// Compute open order details from positional flags, see `open_order` for the typed request
     /// If pay_amount = 0, quantity > 0 => use quantity to calculate
     /// if pay_amount > 0, quantity = 0 => use pay_amount to calculate
     /// If pay_amount > 0, quantity > 0 => prefer quantity over pay_amount
@@ -142,39 +202,78 @@ impl FuturesOrderCalculation {
         is_quote: bool,
         is_buy: bool,
         use_percentage: bool,
-    ) -> anyhow::Result<FuturesOrder> {
-        assert!(self.leverage != Decimal::ZERO, "Leverage not set. Must init new pare first");
-        assert!(self.max_notional != Decimal::ZERO, "Max notional not set. Must init new pare first");
-        assert!(!String::is_empty(&self.collateral_long_token), "Long collateral token not set. Must init new pare first");
-        assert!(!String::is_empty(&self.collateral_short_token), "Short collateral token not set. Must init new pare first");
+    ) -> CoreResult<FuturesOrder> {
+        self.check_config()?;
         crate::clg!("pay amount {:?}, quantity {}", pay_amount, quantity);
-        assert(pay_amount > Decimal::ZERO || quantity > Decimal::ZERO, "Must have positive pay_amount or quantity".to_string()).unwrap();
+        if pay_amount <= Decimal::ZERO && quantity <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput("Must have positive pay_amount or quantity".to_string()));
+        }
 
         let zero = Decimal::ZERO;
         // let balance = self.account_balance.get(&pay_token).unwrap_or(&zero);
-        // TODO Convert balance to quote balance
         let quote_balance = balance;
         let mut quantity = quantity;
         let mut is_quote = is_quote;
         if quantity == zero && pay_amount > zero {
             // todo use pay_amount to calculate the quantity
-            quantity = pay_amount * self.leverage;
+            quantity = checked_mul(pay_amount, self.leverage)?;
             // should auto be quote
             is_quote = true;
         }
 
         // Convert the percentage quantity to an absolute value if necessary
         quantity = if use_percentage {
-            (balance * self.leverage) * quantity
+            checked_mul(checked_mul(balance, self.leverage)?, quantity)?
         } else {
             quantity
         };
+        if use_percentage && balance == zero {
+            // a percentage of an empty balance
+            return Ok(FuturesOrder::empty2(zero));
+        }
+        if quantity <= zero {
+            return Err(CoreError::InvalidInput(format!("Order quantity must be positive, got {}", quantity)));
+        }
+
+        let quantity = if is_quote { QuantitySpec::Quote(quantity) } else { QuantitySpec::Base(quantity) };
+        let side = if is_buy { Side::Bid } else { Side::Ask };
+        let request = OpenOrderRequest { side, quantity, order_type, limit_price };
+        self.open_order(order_book, &request, quote_balance)
+    }
+
+    /// Compute open order details of `request`, `balance` being the quote balance available as margin.
+    /// `QuantitySpec::PercentOfBalance` with no balance returns an empty order, not an error
+    pub fn open_order(&self, order_book: &OrderBook, request: &OpenOrderRequest, balance: Decimal) -> CoreResult<FuturesOrder> {
+        self.check_config()?;
+        let zero = Decimal::ZERO;
+        // TODO Convert balance to quote balance
+        let quote_balance = balance;
+        let (quantity, is_quote) = match request.quantity {
+            QuantitySpec::Base(quantity) => (quantity, false),
+            QuantitySpec::Quote(quantity) => (quantity, true),
+            QuantitySpec::PayAmount(pay_amount) => (checked_mul(pay_amount, self.leverage)?, true),
+            QuantitySpec::PercentOfBalance(share) => (checked_mul(checked_mul(balance, self.leverage)?, share)?, true),
+        };
+        if request.quantity.amount() <= zero {
+            return Err(CoreError::InvalidInput(format!("Order quantity must be positive, got {:?}", request.quantity)));
+        }
+        if matches!(request.quantity, QuantitySpec::PercentOfBalance(_)) && balance == zero {
+            // a percentage of an empty balance opens nothing, priced as an empty order
+            return Ok(FuturesOrder::empty2(zero));
+        }
+        if quantity <= zero {
+            return Err(CoreError::InvalidInput(format!("Order quantity must be positive, got {}", quantity)));
+        }
+        let (order_type, limit_price, is_buy) = (request.order_type, request.limit_price, request.is_buy());
 
-        let (entry_price, total_base_filled, slippage) = match order_type {
-            OrderType::Market => order_book.compute_dry(quantity, is_quote, is_buy),
+        let (entry_price, total_base_filled, slippage, open_fees_rate) = match order_type {
+            OrderType::Market => {
+                let (entry_price, total_base_filled, slippage) = order_book.compute_dry(quantity, is_quote, is_buy)?;
+                (entry_price, total_base_filled, slippage, self.taker_fee)
+            }
             OrderType::Limit => {
-                // For limit orders, use the provided quantity and slippage
-                (limit_price.unwrap(), if is_quote {quantity / limit_price.unwrap()} else {quantity}, dec!(0))
+                let limit_price = limit_price.ok_or_else(|| CoreError::InvalidInput("Limit order without a limit price".to_string()))?;
+                self.compute_limit_fill(order_book, quantity, limit_price, is_quote, is_buy)?
             }
         };
         let total_base_filled = total_base_filled.round_dp_with_strategy(self.base_token_precision, RoundingStrategy::ToZero);
@@ -188,10 +287,6 @@ impl FuturesOrderCalculation {
 
         let open_notional = total_base_filled * entry_price;
 
-        let open_fees_rate = match order_type {
-            OrderType::Limit => self.maker_fee,
-            OrderType::Market => self.taker_fee,
-        };
         clg!("open_fees_rate: {}, ordertype isLimit {}", open_fees_rate, matches!(order_type, OrderType::Limit));
 
         let open_fee = open_fees_rate * open_notional;
@@ -250,6 +345,59 @@ impl FuturesOrderCalculation {
         )
     }
 
+    /// Price a limit order against the book.
+    /// A marketable limit fills as taker up to the limit price and the rest rests on the book
+    /// as maker at the limit price. A non marketable limit rests entirely as maker.
+    /// Returns (entry_price, total_base, slippage, blended fee rate).
+    /// A non positive quantity or limit price is `InvalidInput`
+    pub fn compute_limit_fill(
+        &self,
+        order_book: &OrderBook,
+        quantity: Decimal,
+        limit_price: Decimal,
+        is_quote: bool,
+        is_buy: bool,
+    ) -> CoreResult<(Decimal, Decimal, Decimal, Decimal)> {
+        if limit_price <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Limit price must be positive, got {}", limit_price)));
+        }
+        let taker = match order_book.compute_capped_fill(quantity, is_quote, is_buy, PriceCap::LimitPrice(limit_price)) {
+            Ok(taker) => taker,
+            // nothing to take, the whole order rests
+            Err(CoreError::EmptyBook(_)) => FillEstimate::empty(quantity),
+            Err(error) => return Err(error),
+        };
+        let resting_base = if is_quote { taker.unfilled / limit_price } else { taker.unfilled };
+        if taker.filled_base.is_zero() {
+            return Ok((limit_price, resting_base, dec!(0), self.maker_fee));
+        }
+
+        let resting_quote = resting_base * limit_price;
+        let total_base = taker.filled_base + resting_base;
+        let total_quote = taker.filled_quote + resting_quote;
+        let entry_price = (total_quote / total_base).round_dp(9);
+        let fee_rate = (self.taker_fee * taker.filled_quote + self.maker_fee * resting_quote) / total_quote;
+        Ok((entry_price, total_base, taker.slippage, fee_rate))
+    }
+
+    /// Market order walk with the pair taker fee included, see `OrderBook::compute_dry_with_fee`.
+    /// The filled base is cut to `base_token_precision` like in `compute_open_order`
+    pub fn compute_dry_with_fees(
+        &self,
+        order_book: &OrderBook,
+        fill_amount: Decimal,
+        is_quote: bool,
+        is_buy: bool,
+    ) -> CoreResult<FeeInclusiveFill> {
+        let fill = order_book.compute_dry_with_fee(fill_amount, is_quote, is_buy, self.taker_fee)?;
+        let filled_base = fill.filled_base.round_dp_with_strategy(self.base_token_precision, RoundingStrategy::ToZero);
+        if filled_base == fill.filled_base {
+            return Ok(fill);
+        }
+        // reprice the base that can actually be traded
+        order_book.compute_dry_with_fee(filled_base, false, is_buy, self.taker_fee)
+    }
+
     pub fn compute_margin(&self, quantity: Decimal, entry_price: Decimal) -> Decimal {
         quantity * entry_price / self.leverage
     }
@@ -258,17 +406,36 @@ impl FuturesOrderCalculation {
     //     self.account_balance.insert(self.collateral_long_token.clone(), balance);
     // }
 
-    pub fn change_leverage(&mut self, leverage: Decimal, max_notional: String) {
+    pub fn change_leverage(&mut self, leverage: Decimal, max_notional: String) -> CoreResult<()> {
+        if leverage <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Leverage must be positive, got {}", leverage)));
+        }
+        self.max_notional = string_to_decimal(&max_notional, "Invalid max notional")?;
         self.leverage = leverage;
-        self.max_notional = string_to_decimal(&max_notional, "Invalid max notional");
+        Ok(())
     }
-}
 
-fn assert(condition: bool, message: String) -> anyhow::Result<()> {
-    if !condition {
-        anyhow::bail!(message)
+    // Pair configuration `compute_open_order` cannot work without
+    fn check_config(&self) -> CoreResult<()> {
+        if self.leverage.is_zero() {
+            return Err(CoreError::MissingConfig("Leverage not set. Must init new pair first".to_string()));
+        }
+        if self.max_notional.is_zero() {
+            return Err(CoreError::MissingConfig("Max notional not set. Must init new pair first".to_string()));
+        }
+        if self.collateral_long_token.is_empty() {
+            return Err(CoreError::MissingConfig("Long collateral token not set. Must init new pair first".to_string()));
+        }
+        if self.collateral_short_token.is_empty() {
+            return Err(CoreError::MissingConfig("Short collateral token not set. Must init new pair first".to_string()));
+        }
+        Ok(())
     }
-    Ok(())
+}
+
+fn checked_mul(left: Decimal, right: Decimal) -> CoreResult<Decimal> {
+    left.checked_mul(right)
+        .ok_or_else(|| CoreError::ArithmeticOverflow(format!("{} * {} overflows", left, right)))
 }
 
 
@@ -301,7 +468,7 @@ mod tests {
             (dec!(9600), dec!(1)),
             (dec!(9500), dec!(1)),
         ];
-        ob.initialize(asks, bids);
+        ob.initialize(asks, bids, None);
         ob
     }
 
@@ -315,6 +482,7 @@ mod tests {
             margin_ratio: dec!(0.03),
             taker_fee: dec!(0.001),
             maker_fee: dec!(0.0005),
+            base_token_precision: 8,
         }
     }
 
@@ -334,10 +502,10 @@ mod tests {
             false,
             true,
             false,
-        );
+        ).unwrap();
         assert_eq!(result.entry_price, dec!(10000));
         assert_eq!(result.liquidation_price, dec!(9030));
-        assert_eq!(result.max_quantity_quote, dec!(9990.0));
+        assert_eq!(result.max_quantity_quote, dec!(50000));
         assert_eq!(result.max_quantity_base.round_dp(4), dec!(0.999));
         assert_eq!(result.min_quantity_base, dec!(0.001));
         assert_eq!(result.min_quantity_quote, dec!(10));
@@ -364,11 +532,11 @@ mod tests {
             false,
             true,
             false,
-        );
+        ).unwrap();
 
         assert_eq!(result.entry_price, dec!(10000));
         assert_eq!(result.liquidation_price, dec!(9030));
-        assert_eq!(result.max_quantity_quote, dec!(9990.0));
+        assert_eq!(result.max_quantity_quote, dec!(50000));
         assert_eq!(result.max_quantity_base.round_dp(4), dec!(0.999));
         assert_eq!(result.min_quantity_base, dec!(0.001));
         assert_eq!(result.min_quantity_quote, dec!(10));
@@ -376,7 +544,7 @@ mod tests {
         assert_eq!(result.slippage, dec!(0));
         assert_eq!(result.cost_long, dec!(100));
         assert_eq!(result.cost_short, dec!(100));
-        futures_order_calculation.change_leverage(dec!(20));
+        futures_order_calculation.change_leverage(dec!(20), "50000".to_string()).unwrap();
         let result = futures_order_calculation.compute_open_order(
             OrderType::Market,
             &order_book,
@@ -387,7 +555,7 @@ mod tests {
             false,
             true,
             false,
-        );
+        ).unwrap();
 
         assert_eq!(result.cost_long, dec!(50));
         assert_eq!(result.cost_short, dec!(50));
@@ -410,18 +578,18 @@ mod tests {
             false,
             false,
             false,
-        );
+        ).unwrap();
 
         assert_eq!(result.entry_price, dec!(9900));
         assert_eq!(result.liquidation_price, dec!(10860.3));
-        assert_eq!(result.max_quantity_base, dec!(1.01010101));
+        assert_eq!(result.max_quantity_base, dec!(1.00909090));
         assert_eq!(result.min_quantity_base, dec!(0.001));
-        assert_eq!(result.max_quantity_quote, dec!(9990.0));
+        assert_eq!(result.max_quantity_quote, dec!(50000));
         assert_eq!(result.min_quantity_quote, dec!(9.9));
-        assert_eq!(result.fees, dec!(99.99));
+        assert_eq!(result.fees, dec!(0.9900));
         assert_eq!(result.slippage, dec!(0));
         assert_eq!(result.cost_long, dec!(99));
-        assert_eq!(result.cost_short, dec!(0));
+        assert_eq!(result.cost_short, dec!(99.0));
     }
 
     #[test]
@@ -440,18 +608,18 @@ mod tests {
             false,
             true,
             false,
-        );
+        ).unwrap();
 
         assert_eq!(result.entry_price, dec!(9500));
-        assert_eq!(result.liquidation_price, dec!(9000));
-        assert_eq!(result.max_quantity_base, dec!(1.0526315789473684));
+        assert_eq!(result.liquidation_price, dec!(8578.50));
+        assert_eq!(result.max_quantity_base, dec!(1.05210526));
         assert_eq!(result.min_quantity_base, dec!(0.001));
-        assert_eq!(result.max_quantity_quote, dec!(10000));
+        assert_eq!(result.max_quantity_quote, dec!(50000));
         assert_eq!(result.min_quantity_quote, dec!(9.5));
-        assert_eq!(result.fees, dec!(0.95));
+        assert_eq!(result.fees, dec!(0.47500));
         assert_eq!(result.slippage, dec!(0));
-        assert_eq!(result.cost_long, dec!(9.5));
-        assert_eq!(result.cost_short, dec!(9.5));
+        assert_eq!(result.cost_long, dec!(95.0));
+        assert_eq!(result.cost_short, dec!(95.0));
     }
     #[test]
 fn test_limit_order_sell() {
@@ -469,18 +637,18 @@ fn test_limit_order_sell() {
         false,
         false,
         false,
-    );
+    ).unwrap();
 
     assert_eq!(result.entry_price, dec!(10500));
-    assert_eq!(result.liquidation_price, dec!(12600));
-    assert_eq!(result.max_quantity_base, dec!(0.9523809523809523));
+    assert_eq!(result.liquidation_price, dec!(11518.50));
+    assert_eq!(result.max_quantity_base, dec!(0.95190476));
     assert_eq!(result.min_quantity_base, dec!(0.001));
-    assert_eq!(result.max_quantity_quote, dec!(10000));
+    assert_eq!(result.max_quantity_quote, dec!(50000));
     assert_eq!(result.min_quantity_quote, dec!(10.5));
-    assert_eq!(result.fees, dec!(1.05));
+    assert_eq!(result.fees, dec!(0.52500));
     assert_eq!(result.slippage, dec!(0));
-    assert_eq!(result.cost_long, dec!(10.5));
-    assert_eq!(result.cost_short, dec!(10.5));
+    assert_eq!(result.cost_long, dec!(105.0));
+    assert_eq!(result.cost_short, dec!(105.0));
 }
 
 #[test]
@@ -499,13 +667,13 @@ fn test_market_order_buy_quote() {
         true,
         true,
         false,
-    );
+    ).unwrap();
 
     assert_eq!(result.entry_price, dec!(10000));
     assert_eq!(result.liquidation_price, dec!(9030));
     assert_eq!(result.max_quantity_base, dec!(0.999));
     assert_eq!(result.min_quantity_base, dec!(0.001));
-    assert_eq!(result.max_quantity_quote, dec!(9990));
+    assert_eq!(result.max_quantity_quote, dec!(50000));
     assert_eq!(result.min_quantity_quote, dec!(10));
     assert_eq!(result.fees, dec!(1));
     assert_eq!(result.slippage, dec!(0));
@@ -529,18 +697,18 @@ fn test_market_order_sell_quote() {
         true,
         false,
         false,
-    );
+    ).unwrap();
 
-    assert_eq!(result.entry_price, dec!(10000));
-    assert_eq!(result.liquidation_price, dec!(12000));
-    assert_eq!(result.max_quantity_base, dec!(1));
+    assert_eq!(result.entry_price, dec!(9900));
+    assert_eq!(result.liquidation_price, dec!(10860.30));
+    assert_eq!(result.max_quantity_base, dec!(1.00909090));
     assert_eq!(result.min_quantity_base, dec!(0.001));
-    assert_eq!(result.max_quantity_quote, dec!(10000));
-    assert_eq!(result.min_quantity_quote, dec!(10));
-    assert_eq!(result.fees, dec!(1));
+    assert_eq!(result.max_quantity_quote, dec!(50000));
+    assert_eq!(result.min_quantity_quote, dec!(9.900));
+    assert_eq!(result.fees, dec!(0.00009999000));
     assert_eq!(result.slippage, dec!(0));
-    assert_eq!(result.cost_long, dec!(10));
-    assert_eq!(result.cost_short, dec!(10));
+    assert_eq!(result.cost_long, dec!(0.00999900));
+    assert_eq!(result.cost_short, dec!(0.00999900));
     }
 
     #[test]
@@ -559,18 +727,18 @@ fn test_limit_order_buy_quote() {
         true,
         true,
         false,
-    );
+    ).unwrap();
 
     assert_eq!(result.entry_price, dec!(10000));
-    assert_eq!(result.liquidation_price, dec!(8333.333333333333333333333333));
-    assert_eq!(result.max_quantity_base, dec!(1));
+    assert_eq!(result.liquidation_price, dec!(9030.00));
+    assert_eq!(result.max_quantity_base, dec!(0.999));
     assert_eq!(result.min_quantity_base, dec!(0.001));
-    assert_eq!(result.max_quantity_quote, dec!(10000));
+    assert_eq!(result.max_quantity_quote, dec!(50000));
     assert_eq!(result.min_quantity_quote, dec!(10));
-    assert_eq!(result.fees, dec!(0.1));
+    assert_eq!(result.fees, dec!(0.00050000));
     assert_eq!(result.slippage, dec!(0));
-    assert_eq!(result.cost_long, dec!(10));
-    assert_eq!(result.cost_short, dec!(10));
+    assert_eq!(result.cost_long, dec!(0.05000));
+    assert_eq!(result.cost_short, dec!(0.05000));
 }
 
 #[test]
@@ -588,20 +756,200 @@ fn test_limit_order_sell_quote() {
         true,
         false,
         false,
-    );
+    ).unwrap();
 
     assert_eq!(result.entry_price, dec!(10000));
-    assert_eq!(result.liquidation_price, dec!(12500));
-    assert_eq!(result.max_quantity_base, dec!(1));
+    assert_eq!(result.liquidation_price, dec!(10970.00));
+    assert_eq!(result.max_quantity_base, dec!(0.9995));
     assert_eq!(result.min_quantity_base, dec!(0.001));
-    assert_eq!(result.max_quantity_quote, dec!(10000));
+    assert_eq!(result.max_quantity_quote, dec!(50000));
     assert_eq!(result.min_quantity_quote, dec!(10));
-    assert_eq!(result.fees, dec!(0.1));
+    assert_eq!(result.fees, dec!(0.000250000));
     assert_eq!(result.slippage, dec!(0));
-    assert_eq!(result.cost_long, dec!(10));
-    assert_eq!(result.cost_short, dec!(10));
+    assert_eq!(result.cost_long, dec!(0.05000));
+    assert_eq!(result.cost_short, dec!(0.05000));
 }
 
+    fn open_limit(limit_price: Decimal, quantity: Decimal, is_quote: bool, is_buy: bool) -> FuturesOrder {
+        setup_futures_order_calculation().compute_open_order(
+            OrderType::Limit,
+            &setup_order_book(),
+            dec!(100000),
+            dec!(0),
+            quantity,
+            Some(limit_price),
+            is_quote,
+            is_buy,
+            false,
+        ).unwrap()
+    }
 
-}
+    #[test]
+    fn test_non_marketable_limit_rests_as_maker() {
+        let result = open_limit(dec!(9500), dec!(0.1), false, true);
+        assert_eq!(result.entry_price, dec!(9500));
+        assert_eq!(result.open_quantity, dec!(0.1));
+        assert_eq!(result.fees, dec!(0.475));
+        assert_eq!(result.slippage, dec!(0));
+    }
+
+    #[test]
+    fn test_marketable_limit_fills_up_to_limit_then_rests() {
+        // 1 @ 10000 + 1 @ 10100 taken, 0.5 rests @ 10100
+        let result = open_limit(dec!(10100), dec!(2.5), false, true);
+        assert_eq!(result.entry_price, dec!(10060));
+        assert_eq!(result.open_quantity, dec!(2.5));
+        // 0.001 * 20100 taker + 0.0005 * 5050 maker
+        assert_eq!(result.fees.round_dp(9), dec!(22.625));
+        assert_eq!(result.slippage, dec!(0.5));
+    }
+
+    #[test]
+    fn test_marketable_limit_fully_taken() {
+        let result = open_limit(dec!(9800), dec!(4950), true, false);
+        assert_eq!(result.entry_price, dec!(9900));
+        assert_eq!(result.open_quantity, dec!(0.5));
+        assert_eq!(result.fees, dec!(4.95));
+        assert_eq!(result.slippage, dec!(0));
+    }
+
+    #[test]
+    fn test_compute_dry_with_fees_uses_pair_config() {
+        let order_book = setup_order_book();
+        let calculation = FuturesOrderCalculation { base_token_precision: 3, ..setup_futures_order_calculation() };
+
+        let fill = calculation.compute_dry_with_fees(&order_book, dec!(1.5), false, true).unwrap();
+        assert_eq!(fill.notional, dec!(15050));
+        assert_eq!(fill.fee.amount, dec!(15.05));
+        assert_eq!(fill.net_quote, dec!(15065.05));
+
+        // 5000 spent fees included buys 0.499500499.. base, cut to 0.499
+        let fill = calculation.compute_dry_with_fees(&order_book, dec!(5000), true, true).unwrap();
+        assert_eq!(fill.filled_base, dec!(0.499));
+        assert_eq!(fill.net_quote, dec!(4994.99));
+    }
+
+    fn open_with_flags(calculation: &FuturesOrderCalculation, pay_amount: Decimal, quantity: Decimal, order_type: OrderType, limit_price: Option<Decimal>) -> CoreResult<FuturesOrder> {
+        calculation.compute_open_order(order_type, &setup_order_book(), dec!(1000), pay_amount, quantity, limit_price, false, true, false)
+    }
+
+    #[test]
+    fn test_errors_instead_of_panics() {
+        let error = open_with_flags(&FuturesOrderCalculation::default(), dec!(0), dec!(1), OrderType::Market, None).unwrap_err();
+        assert_eq!(error.code(), "MISSING_CONFIG");
+
+        let calculation = FuturesOrderCalculation::new(
+            "USDT".to_string(), "USDT".to_string(), "10".to_string(), "50000".to_string(),
+            "0.001".to_string(), "0.03".to_string(), "0.001".to_string(), "0.0005".to_string(), 8,
+        ).unwrap();
+        assert_eq!(open_with_flags(&calculation, dec!(0), dec!(0), OrderType::Market, None).unwrap_err().code(), "INVALID_INPUT");
+        assert_eq!(open_with_flags(&calculation, dec!(0), dec!(1), OrderType::Limit, None).unwrap_err().code(), "INVALID_INPUT");
+        assert_eq!(open_with_flags(&calculation, Decimal::MAX, dec!(0), OrderType::Market, None).unwrap_err().code(), "ARITHMETIC_OVERFLOW");
+        assert!(open_with_flags(&calculation, dec!(0), dec!(0.5), OrderType::Limit, Some(dec!(9000))).is_ok());
+
+        let error = FuturesOrderCalculation::new(
+            "USDT".to_string(), "USDT".to_string(), "ten".to_string(), "50000".to_string(),
+            "0.001".to_string(), "0.03".to_string(), "0.001".to_string(), "0.0005".to_string(), 8,
+        ).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid leverage"));
+        let error = FuturesOrderCalculation::new(
+            "USDT".to_string(), "USDT".to_string(), "10".to_string(), "50000".to_string(),
+            "0.001".to_string(), "0.03".to_string(), "0.001".to_string(), "0.0005".to_string(), 29,
+        ).unwrap_err();
+        assert_eq!(error.code(), "INVALID_PRECISION");
+    }
+
+    fn open_request(request: OpenOrderRequest) -> FuturesOrder {
+        setup_futures_order_calculation().open_order(&setup_order_book(), &request, dec!(1000)).unwrap()
+    }
 
+    #[test]
+    fn test_quantity_specs() {
+        let base = open_request(OpenOrderRequest::market(Side::Bid, QuantitySpec::Base(dec!(0.1))));
+        assert_eq!(base.entry_price, dec!(10000));
+        assert_eq!(base.open_quantity, dec!(0.1));
+
+        // 99 of margin at 10x, 9.9% of 1000 at 10x and 990 of notional are the same order
+        for quantity in [QuantitySpec::PayAmount(dec!(99)), QuantitySpec::PercentOfBalance(dec!(0.099)), QuantitySpec::Quote(dec!(990))] {
+            let order = open_request(OpenOrderRequest::market(Side::Ask, quantity));
+            assert_eq!(order.entry_price, dec!(9900));
+            assert_eq!(order.open_quantity, dec!(0.1));
+        }
+
+        let limit = open_request(OpenOrderRequest::limit(Side::Bid, QuantitySpec::Base(dec!(0.1)), dec!(9500)));
+        assert_eq!(limit.entry_price, dec!(9500));
+        assert_eq!(limit.fees, dec!(0.475));
+
+        let error = setup_futures_order_calculation()
+            .open_order(&setup_order_book(), &OpenOrderRequest::market(Side::Bid, QuantitySpec::Base(dec!(0))), dec!(1000))
+            .unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_percent_of_empty_balance_is_empty_order() {
+        let calculation = setup_futures_order_calculation();
+        let request = OpenOrderRequest::market(Side::Bid, QuantitySpec::PercentOfBalance(dec!(0.5)));
+        let order = calculation.open_order(&setup_order_book(), &request, dec!(0)).unwrap();
+        assert_eq!(order.open_quantity, dec!(0));
+        assert_eq!(order.entry_price, dec!(0));
+
+        let order = calculation
+            .compute_open_order(OrderType::Market, &setup_order_book(), dec!(0), dec!(0), dec!(0.5), None, true, true, true)
+            .unwrap();
+        assert_eq!(order.open_quantity, dec!(0));
+    }
+
+    #[test]
+    fn test_limit_fill_on_an_empty_side_rests() {
+        let calculation = setup_futures_order_calculation();
+        let (entry_price, total_base, slippage, fee_rate) =
+            calculation.compute_limit_fill(&OrderBook::new(), dec!(0.1), dec!(9500), false, true).unwrap();
+        assert_eq!((entry_price, total_base, slippage), (dec!(9500), dec!(0.1), dec!(0)));
+        assert_eq!(fee_rate, calculation.maker_fee);
+
+        let error = calculation.compute_limit_fill(&OrderBook::new(), dec!(0.1), dec!(0), false, true).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_invalid_explicit_quantity_is_rejected() {
+        let calculation = setup_futures_order_calculation();
+        // a negative quantity next to a positive pay amount still takes the quantity
+        let error = calculation
+            .compute_open_order(OrderType::Market, &setup_order_book(), dec!(1000), dec!(100), dec!(-1), None, false, true, false)
+            .unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+
+        // a percentage of a negative balance is not an empty order
+        let error = calculation
+            .compute_open_order(OrderType::Market, &setup_order_book(), dec!(-1000), dec!(0), dec!(0.5), None, true, true, true)
+            .unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        let request = OpenOrderRequest::market(Side::Bid, QuantitySpec::PercentOfBalance(dec!(0.5)));
+        let error = calculation.open_order(&setup_order_book(), &request, dec!(-1000)).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_matches_positional_flags() {
+        let calculation = setup_futures_order_calculation();
+        let request = OpenOrderRequest::market(Side::Ask, QuantitySpec::Quote(dec!(15000)));
+        let typed = calculation.open_order(&setup_order_book(), &request, dec!(1000)).unwrap();
+        let positional = calculation
+            .compute_open_order(OrderType::Market, &setup_order_book(), dec!(1000), dec!(0), dec!(15000), None, true, false, false)
+            .unwrap();
+        assert_eq!(typed.entry_price, positional.entry_price);
+        assert_eq!(typed.open_quantity, positional.open_quantity);
+        assert_eq!(typed.liquidation_price, positional.liquidation_price);
+        assert_eq!(typed.fees, positional.fees);
+    }
+
+    #[test]
+    fn test_request_from_json() {
+        let request: OpenOrderRequest = serde_json::from_str(
+            r#"{"side":"Bid","quantity":{"type":"pay_amount","value":"100"},"order_type":"Market"}"#,
+        ).unwrap();
+        assert_eq!(request, OpenOrderRequest::market(Side::Bid, QuantitySpec::PayAmount(dec!(100))));
+    }
+}
@@ -0,0 +1,513 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use super::order::{FuturesOrderCalculation, OpenOrderRequest, QuantitySpec};
+use crate::error::{CoreError, CoreResult};
+use crate::orderbook::{OrderBook, Side};
+
+/// An open futures position with isolated margin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    /// `Side::Bid` for a long, `Side::Ask` for a short
+    pub side: Side,
+    /// Size in base, always positive
+    pub size: Decimal,
+    pub entry_price: Decimal,
+    /// Margin isolated in the position, in quote
+    pub margin: Decimal,
+    pub leverage: Decimal,
+}
+
+impl Position {
+    /// Position opened at `entry_price` with the margin `leverage` requires.
+    /// Size, entry price and leverage must be positive
+    pub fn new(side: Side, size: Decimal, entry_price: Decimal, leverage: Decimal) -> CoreResult<Self> {
+        check_terms(size, entry_price, leverage)?;
+        let margin = size * entry_price / leverage;
+        Ok(Self { side, size, entry_price, margin, leverage })
+    }
+
+    pub fn is_long(&self) -> bool {
+        !self.side.is_ask()
+    }
+
+    pub fn notional(&self) -> Decimal {
+        self.size * self.entry_price
+    }
+
+    /// Price at which the margin left over the maintenance margin (`margin * margin_ratio`)
+    /// is lost, same model as `compute_open_order`
+    pub fn liquidation_price(&self, margin_ratio: Decimal) -> Decimal {
+        let maintenance_margin = self.margin * margin_ratio;
+        let buffer = (self.margin - maintenance_margin) / self.size;
+        let liquidation_price = if self.is_long() { self.entry_price - buffer } else { self.entry_price + buffer };
+        liquidation_price.round_dp(9)
+    }
+
+    /// PnL of closing `size` of the position at `price`, fees excluded
+    pub fn pnl_at(&self, size: Decimal, price: Decimal) -> Decimal {
+        if self.is_long() {
+            size * (price - self.entry_price)
+        } else {
+            size * (self.entry_price - price)
+        }
+    }
+
+    // a position built outside `new`, e.g. deserialized from JS, may carry any margin
+    fn check(&self) -> CoreResult<()> {
+        check_terms(self.size, self.entry_price, self.leverage)?;
+        if self.margin <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Position margin must be positive, got {}", self.margin)));
+        }
+        Ok(())
+    }
+}
+
+fn check_terms(size: Decimal, entry_price: Decimal, leverage: Decimal) -> CoreResult<()> {
+    if size <= Decimal::ZERO || entry_price <= Decimal::ZERO || leverage <= Decimal::ZERO {
+        return Err(CoreError::InvalidInput(format!(
+            "Position size {}, entry price {} and leverage {} must be positive",
+            size, entry_price, leverage
+        )));
+    }
+    Ok(())
+}
+
+/// Execution of a new order, as priced by `open_order`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderFill {
+    pub side: Side,
+    /// Size in base, always positive
+    pub size: Decimal,
+    pub price: Decimal,
+    /// Fees paid on the whole fill, in quote
+    pub fee: Decimal,
+}
+
+/// Outcome of netting an order into a position.
+///
+/// The part of the order against the position closes it first (`closed_size`, `realized_pnl`,
+/// `margin_released`), what is left adds to it or, past zero, opens the other side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionChange {
+    /// Resulting position, `None` when flat
+    pub position: Option<Position>,
+    pub liquidation_price: Option<Decimal>,
+    pub closed_size: Decimal,
+    /// PnL of the closed part at the fill price, fees excluded
+    pub realized_pnl: Decimal,
+    /// Margin freed by the closed part
+    pub margin_released: Decimal,
+    pub fees: Decimal,
+}
+
+/// How much of a position to close
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum CloseQuantity {
+    /// Size in base, capped at the position size
+    Size(Decimal),
+    /// Share of the position, 0.25 = 25%, capped at 1
+    Percent(Decimal),
+}
+
+/// Expected outcome of a reduce-only order closing part or all of a position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloseOrder {
+    /// Average exit price, the limit price for the part resting as maker
+    pub exit_price: Decimal,
+    pub closed_size: Decimal,
+    /// Slippage of the part taken from the book, in 100%: 10 = 10%
+    pub slippage: Decimal,
+    /// PnL of the closed part, fees excluded
+    pub realized_pnl: Decimal,
+    pub fees: Decimal,
+    pub margin_released: Decimal,
+    /// What is left of the position, `None` once fully closed
+    pub remaining: Option<Position>,
+    pub liquidation_price: Option<Decimal>,
+}
+
+/// Live figures of a position marked at a price, rounded to 9 decimals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionMetrics {
+    /// Price the position is valued at, the mark price or the book exit price
+    pub mark_price: Decimal,
+    /// PnL of closing the whole position at `mark_price`, fees excluded
+    pub unrealized_pnl: Decimal,
+    /// Return on the margin, in 100%: 10 = 10%
+    pub roe: Decimal,
+    /// Maintenance margin over equity (margin + unrealized PnL), in 100%: the position is
+    /// liquidated at 100. `None` once the equity is gone
+    pub margin_ratio: Option<Decimal>,
+    pub liquidation_price: Decimal,
+    /// How far `mark_price` can move against the position before liquidation, in quote
+    pub liquidation_distance: Decimal,
+    /// `liquidation_distance` against `mark_price`, in 100%: 10 = 10%
+    pub liquidation_distance_pct: Decimal,
+}
+
+impl FuturesOrderCalculation {
+    /// Net `fill` into `position` (`None` when flat). Added size takes the leverage of the
+    /// position, a new or flipped position the leverage of the pair
+    pub fn net_fill(&self, position: Option<&Position>, fill: &OrderFill) -> CoreResult<PositionChange> {
+        if self.leverage <= Decimal::ZERO {
+            return Err(CoreError::MissingConfig("Leverage not set. Must init new pair first".to_string()));
+        }
+        if fill.size <= Decimal::ZERO || fill.price <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Fill size {} and price {} must be positive", fill.size, fill.price)));
+        }
+
+        let mut change = PositionChange {
+            position: None,
+            liquidation_price: None,
+            closed_size: Decimal::ZERO,
+            realized_pnl: Decimal::ZERO,
+            margin_released: Decimal::ZERO,
+            fees: fill.fee,
+        };
+        let position = match position {
+            Some(position) => {
+                position.check()?;
+                position
+            }
+            None => {
+                change.position = Some(Position::new(fill.side, fill.size, fill.price, self.leverage)?);
+                return Ok(self.with_liquidation(change));
+            }
+        };
+
+        if position.side == fill.side {
+            let size = position.size + fill.size;
+            let entry_price = ((position.notional() + fill.size * fill.price) / size).round_dp(9);
+            let margin = position.margin + fill.size * fill.price / position.leverage;
+            change.position = Some(Position { side: position.side, size, entry_price, margin, leverage: position.leverage });
+            return Ok(self.with_liquidation(change));
+        }
+
+        let closed_size = fill.size.min(position.size);
+        change.closed_size = closed_size;
+        change.realized_pnl = position.pnl_at(closed_size, fill.price).round_dp(9);
+        change.margin_released = (position.margin * closed_size / position.size).round_dp(9);
+        change.position = if fill.size < position.size {
+            Some(Position {
+                size: position.size - closed_size,
+                margin: position.margin - change.margin_released,
+                ..position.clone()
+            })
+        } else if fill.size > position.size {
+            Some(Position::new(fill.side, fill.size - position.size, fill.price, self.leverage)?)
+        } else {
+            None
+        };
+        Ok(self.with_liquidation(change))
+    }
+
+    /// Price `request` against `order_book` with `open_order` and net it into `position`
+    pub fn compute_position_change(
+        &self,
+        order_book: &OrderBook,
+        position: Option<&Position>,
+        request: &OpenOrderRequest,
+        balance: Decimal,
+    ) -> CoreResult<PositionChange> {
+        let order = self.open_order(order_book, request, balance)?;
+        if order.open_quantity.is_zero() {
+            if matches!(request.quantity, QuantitySpec::PercentOfBalance(_)) && balance <= Decimal::ZERO {
+                return Err(CoreError::InvalidInput(format!("No balance to open {:?} of", request.quantity)));
+            }
+            return Err(order_book.unfillable(request.quantity.amount(), request.is_buy()));
+        }
+        let fill = OrderFill { side: request.side, size: order.open_quantity, price: order.entry_price, fee: order.fees };
+        self.net_fill(position, &fill)
+    }
+
+    /// Close `quantity` of `position` at market, walking the opposite side of `order_book`,
+    /// or with a limit order at `limit_price` (taker up to the limit, the rest as maker).
+    /// The order is reduce-only: it never closes more than the position
+    pub fn compute_close_order(
+        &self,
+        order_book: &OrderBook,
+        position: &Position,
+        quantity: CloseQuantity,
+        limit_price: Option<Decimal>,
+    ) -> CoreResult<CloseOrder> {
+        position.check()?;
+        let size = match quantity {
+            CloseQuantity::Size(size) => size.min(position.size),
+            CloseQuantity::Percent(share) => position.size * share.min(Decimal::ONE),
+        };
+        let size = size.round_dp_with_strategy(self.base_token_precision, RoundingStrategy::ToZero);
+        if size <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Close quantity must be positive, got {:?}", quantity)));
+        }
+
+        // closing a long sells into the bids, closing a short buys from the asks
+        let is_buy = position.side.is_ask();
+        let (exit_price, closed_size, slippage, fee_rate) = match limit_price {
+            None => {
+                let (exit_price, closed_size, slippage) = order_book.compute_dry(size, false, is_buy)?;
+                (exit_price, closed_size, slippage, self.taker_fee)
+            }
+            Some(limit_price) => self.compute_limit_fill(order_book, size, limit_price, false, is_buy)?,
+        };
+        // a market spec may round the fill below the requested size, never above the position
+        let closed_size = closed_size.min(position.size);
+
+        let side = if is_buy { Side::Bid } else { Side::Ask };
+        let fee = (closed_size * exit_price * fee_rate).round_dp(9);
+        let change = self.net_fill(Some(position), &OrderFill { side, size: closed_size, price: exit_price, fee })?;
+        Ok(CloseOrder {
+            exit_price,
+            closed_size,
+            slippage,
+            realized_pnl: change.realized_pnl,
+            fees: change.fees,
+            margin_released: change.margin_released,
+            remaining: change.position,
+            liquidation_price: change.liquidation_price,
+        })
+    }
+
+    /// Unrealized PnL, ROE, margin ratio and distance to liquidation of `position` at `mark_price`
+    pub fn position_metrics(&self, position: &Position, mark_price: Decimal) -> CoreResult<PositionMetrics> {
+        position.check()?;
+        if mark_price <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Mark price must be positive, got {}", mark_price)));
+        }
+
+        let unrealized_pnl = position.pnl_at(position.size, mark_price);
+        let equity = position.margin + unrealized_pnl;
+        let maintenance_margin = position.margin * self.margin_ratio;
+        let liquidation_price = position.liquidation_price(self.margin_ratio);
+        // negative once the mark price is past the liquidation price
+        let liquidation_distance = if position.is_long() { mark_price - liquidation_price } else { liquidation_price - mark_price };
+        Ok(PositionMetrics {
+            mark_price,
+            unrealized_pnl: unrealized_pnl.round_dp(9),
+            roe: (unrealized_pnl / position.margin * Decimal::ONE_HUNDRED).round_dp(9),
+            margin_ratio: (equity > Decimal::ZERO).then(|| (maintenance_margin / equity * Decimal::ONE_HUNDRED).round_dp(9)),
+            liquidation_price,
+            liquidation_distance: liquidation_distance.round_dp(9),
+            liquidation_distance_pct: (liquidation_distance / mark_price * Decimal::ONE_HUNDRED).round_dp(9),
+        })
+    }
+
+    /// `position_metrics` marked at the price closing the whole position at market would get,
+    /// walking the opposite side of `order_book`
+    pub fn position_metrics_from_book(&self, order_book: &OrderBook, position: &Position) -> CoreResult<PositionMetrics> {
+        position.check()?;
+        let (exit_price, _, _) = order_book.compute_dry(position.size, false, position.side.is_ask())?;
+        self.position_metrics(position, exit_price)
+    }
+
+    fn with_liquidation(&self, mut change: PositionChange) -> PositionChange {
+        change.liquidation_price = change.position.as_ref().map(|position| position.liquidation_price(self.margin_ratio));
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_futures_order_calculation() -> FuturesOrderCalculation {
+        FuturesOrderCalculation {
+            leverage: dec!(10),
+            collateral_long_token: "USDT".to_string(),
+            collateral_short_token: "USDT".to_string(),
+            max_notional: dec!(50000),
+            min_quantity_base: dec!(0.001),
+            margin_ratio: dec!(0.03),
+            taker_fee: dec!(0.001),
+            maker_fee: dec!(0.0005),
+            base_token_precision: 8,
+        }
+    }
+
+    fn fill(side: Side, size: Decimal, price: Decimal) -> OrderFill {
+        OrderFill { side, size, price, fee: size * price * dec!(0.001) }
+    }
+
+    #[test]
+    fn test_open_and_add() {
+        let calculation = setup_futures_order_calculation();
+        let change = calculation.net_fill(None, &fill(Side::Bid, dec!(1), dec!(10000))).unwrap();
+        let position = change.position.unwrap();
+        assert_eq!(position.margin, dec!(1000));
+        // 10000 - (1000 - 30) / 1
+        assert_eq!(change.liquidation_price, Some(dec!(9030)));
+
+        let change = calculation.net_fill(Some(&position), &fill(Side::Bid, dec!(1), dec!(11000))).unwrap();
+        let position = change.position.unwrap();
+        assert_eq!(position.size, dec!(2));
+        assert_eq!(position.entry_price, dec!(10500));
+        assert_eq!(position.margin, dec!(2100));
+        assert_eq!(change.realized_pnl, dec!(0));
+        assert_eq!(change.fees, dec!(11));
+        // 10500 - (2100 - 63) / 2
+        assert_eq!(change.liquidation_price, Some(dec!(9481.5)));
+    }
+
+    #[test]
+    fn test_reduce_close_and_flip() {
+        let calculation = setup_futures_order_calculation();
+        let short = Position::new(Side::Ask, dec!(2), dec!(10000), dec!(5)).unwrap();
+
+        let change = calculation.net_fill(Some(&short), &fill(Side::Bid, dec!(0.5), dec!(9000))).unwrap();
+        assert_eq!(change.closed_size, dec!(0.5));
+        assert_eq!(change.realized_pnl, dec!(500));
+        assert_eq!(change.margin_released, dec!(1000));
+        let position = change.position.unwrap();
+        assert_eq!((position.side, position.size, position.entry_price, position.margin), (Side::Ask, dec!(1.5), dec!(10000), dec!(3000)));
+
+        let change = calculation.net_fill(Some(&short), &fill(Side::Bid, dec!(2), dec!(10500))).unwrap();
+        assert_eq!(change.realized_pnl, dec!(-1000));
+        assert_eq!(change.position, None);
+        assert_eq!(change.liquidation_price, None);
+
+        // 2 close the short, 1 opens a long at the pair leverage
+        let change = calculation.net_fill(Some(&short), &fill(Side::Bid, dec!(3), dec!(9500))).unwrap();
+        assert_eq!(change.closed_size, dec!(2));
+        assert_eq!(change.realized_pnl, dec!(1000));
+        assert_eq!(change.margin_released, dec!(4000));
+        assert_eq!(change.position, Some(Position::new(Side::Bid, dec!(1), dec!(9500), dec!(10)).unwrap()));
+        assert_eq!(change.fees, dec!(28.5));
+    }
+
+    #[test]
+    fn test_position_change_from_book() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(10000), dec!(1)), (dec!(10100), dec!(1))], vec![(dec!(9900), dec!(1))], None);
+        let calculation = setup_futures_order_calculation();
+        let long = Position::new(Side::Bid, dec!(1), dec!(9000), dec!(10)).unwrap();
+
+        let request = OpenOrderRequest::market(Side::Ask, QuantitySpec::Base(dec!(0.5)));
+        let change = calculation.compute_position_change(&order_book, Some(&long), &request, dec!(1000)).unwrap();
+        assert_eq!(change.realized_pnl, dec!(450));
+        assert_eq!(change.fees, dec!(4.95));
+        assert_eq!(change.position.unwrap().size, dec!(0.5));
+
+        let request = OpenOrderRequest::market(Side::Ask, QuantitySpec::Base(dec!(5)));
+        let error = calculation.compute_position_change(&order_book, Some(&long), &request, dec!(1000)).unwrap_err();
+        assert_eq!(error.code(), "INSUFFICIENT_LIQUIDITY");
+        let request = OpenOrderRequest::market(Side::Ask, QuantitySpec::PercentOfBalance(dec!(0.5)));
+        let error = calculation.compute_position_change(&order_book, Some(&long), &request, dec!(0)).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        assert!(calculation.net_fill(Some(&long), &fill(Side::Ask, dec!(0), dec!(9000))).is_err());
+    }
+
+    #[test]
+    fn test_new_position_rejects_zero_leverage() {
+        let error = Position::new(Side::Bid, dec!(1), dec!(10000), dec!(0)).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        assert!(Position::new(Side::Bid, dec!(0), dec!(10000), dec!(10)).is_err());
+    }
+
+    fn setup_close_book() -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(
+            vec![(dec!(10000), dec!(1)), (dec!(10100), dec!(1))],
+            vec![(dec!(9900), dec!(1)), (dec!(9800), dec!(1))],
+            None,
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_close_at_market() {
+        let calculation = setup_futures_order_calculation();
+        let long = Position::new(Side::Bid, dec!(1.5), dec!(9000), dec!(10)).unwrap();
+
+        let close = calculation.compute_close_order(&setup_close_book(), &long, CloseQuantity::Percent(dec!(1)), None).unwrap();
+        // 1 @ 9900 + 0.5 @ 9800
+        assert_eq!(close.exit_price, dec!(9866.666666667));
+        assert_eq!(close.closed_size, dec!(1.5));
+        assert_eq!(close.realized_pnl, dec!(1300.000000000));
+        assert_eq!(close.fees, dec!(14.8));
+        assert_eq!(close.margin_released, dec!(1350));
+        assert_eq!(close.remaining, None);
+        assert!(close.slippage > dec!(0));
+
+        // reduce-only: asking for more than the position closes the position
+        let capped = calculation.compute_close_order(&setup_close_book(), &long, CloseQuantity::Size(dec!(10)), None).unwrap();
+        assert_eq!(capped.closed_size, dec!(1.5));
+
+        let short = Position::new(Side::Ask, dec!(2), dec!(10500), dec!(5)).unwrap();
+        let close = calculation.compute_close_order(&setup_close_book(), &short, CloseQuantity::Percent(dec!(0.25)), None).unwrap();
+        assert_eq!(close.exit_price, dec!(10000));
+        assert_eq!(close.realized_pnl, dec!(250));
+        assert_eq!(close.margin_released, dec!(1050));
+        let remaining = close.remaining.unwrap();
+        assert_eq!((remaining.size, remaining.margin), (dec!(1.5), dec!(3150)));
+        assert_eq!(close.liquidation_price, Some(remaining.liquidation_price(dec!(0.03))));
+    }
+
+    #[test]
+    fn test_close_with_limit() {
+        let calculation = setup_futures_order_calculation();
+        let long = Position::new(Side::Bid, dec!(1), dec!(9000), dec!(10)).unwrap();
+
+        // not marketable, rests as maker at the limit
+        let close = calculation.compute_close_order(&setup_close_book(), &long, CloseQuantity::Size(dec!(1)), Some(dec!(10500))).unwrap();
+        assert_eq!(close.exit_price, dec!(10500));
+        assert_eq!(close.realized_pnl, dec!(1500));
+        assert_eq!(close.fees, dec!(5.25));
+        assert_eq!(close.slippage, dec!(0));
+
+        assert_eq!(
+            calculation.compute_close_order(&setup_close_book(), &long, CloseQuantity::Size(dec!(0)), None).unwrap_err().code(),
+            "INVALID_INPUT"
+        );
+        assert_eq!(
+            calculation.compute_close_order(&OrderBook::new(), &long, CloseQuantity::Size(dec!(1)), None).unwrap_err().code(),
+            "EMPTY_BOOK"
+        );
+    }
+
+    #[test]
+    fn test_position_metrics() {
+        let calculation = setup_futures_order_calculation();
+        // margin 1000, maintenance 30, liquidation at 10000 - 970
+        let long = Position::new(Side::Bid, dec!(1), dec!(10000), dec!(10)).unwrap();
+        let metrics = calculation.position_metrics(&long, dec!(10500)).unwrap();
+        assert_eq!(metrics.unrealized_pnl, dec!(500));
+        assert_eq!(metrics.roe, dec!(50));
+        assert_eq!(metrics.margin_ratio, Some(dec!(2)));
+        assert_eq!(metrics.liquidation_price, dec!(9030));
+        assert_eq!(metrics.liquidation_distance, dec!(1470));
+        assert_eq!(metrics.liquidation_distance_pct, dec!(14));
+
+        let short = Position::new(Side::Ask, dec!(2), dec!(10000), dec!(5)).unwrap();
+        let metrics = calculation.position_metrics(&short, dec!(10400)).unwrap();
+        assert_eq!(metrics.unrealized_pnl, dec!(-800));
+        assert_eq!(metrics.roe, dec!(-20));
+        assert_eq!(metrics.margin_ratio, Some(dec!(3.75)));
+        assert_eq!(metrics.liquidation_distance, dec!(1540));
+
+        // past liquidation the equity is gone and the distance negative
+        let metrics = calculation.position_metrics(&long, dec!(8900)).unwrap();
+        assert_eq!(metrics.margin_ratio, None);
+        assert_eq!(metrics.liquidation_distance, dec!(-130));
+
+        assert_eq!(calculation.position_metrics(&long, dec!(0)).unwrap_err().code(), "INVALID_INPUT");
+        let no_margin = Position { margin: dec!(0), ..long };
+        assert_eq!(calculation.position_metrics(&no_margin, dec!(10500)).unwrap_err().code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_position_metrics_from_book() {
+        let calculation = setup_futures_order_calculation();
+        let long = Position::new(Side::Bid, dec!(2), dec!(9000), dec!(10)).unwrap();
+        // closing sells 1 @ 9900 and 1 @ 9800
+        let metrics = calculation.position_metrics_from_book(&setup_close_book(), &long).unwrap();
+        assert_eq!(metrics.mark_price, dec!(9850));
+        assert_eq!(metrics, calculation.position_metrics(&long, dec!(9850)).unwrap());
+        assert_eq!(metrics.unrealized_pnl, dec!(1700));
+
+        let large = Position::new(Side::Bid, dec!(3), dec!(9000), dec!(10)).unwrap();
+        let error = calculation.position_metrics_from_book(&setup_close_book(), &large).unwrap_err();
+        assert_eq!(error.code(), "INSUFFICIENT_LIQUIDITY");
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// Error returned by the fallible core APIs.
+///
+/// Every variant carries a human readable message, `code` gives a stable identifier for
+/// callers that branch on the kind of error (the wasm crate exposes it on JS errors)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreError {
+    /// An argument or a message that cannot be used: unparsable number, unknown id, off-grid level, ...
+    InvalidInput(String),
+    /// Configuration that must be set before the call, e.g. the leverage of a pair
+    MissingConfig(String),
+    /// The book has levels but not enough to fill the requested amount
+    InsufficientLiquidity(String),
+    /// The side of the book needed by the call has no level
+    EmptyBook(String),
+    /// A computation went past what a `Decimal` can hold
+    ArithmeticOverflow(String),
+    /// A scale or step size the market or token precision cannot represent
+    InvalidPrecision(String),
+    /// Reading or writing a file or stream failed
+    Io(String),
+}
+
+pub type CoreResult<T> = Result<T, CoreError>;
+
+impl CoreError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoreError::InvalidInput(_) => "INVALID_INPUT",
+            CoreError::MissingConfig(_) => "MISSING_CONFIG",
+            CoreError::InsufficientLiquidity(_) => "INSUFFICIENT_LIQUIDITY",
+            CoreError::EmptyBook(_) => "EMPTY_BOOK",
+            CoreError::ArithmeticOverflow(_) => "ARITHMETIC_OVERFLOW",
+            CoreError::InvalidPrecision(_) => "INVALID_PRECISION",
+            CoreError::Io(_) => "IO",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CoreError::InvalidInput(message)
+            | CoreError::MissingConfig(message)
+            | CoreError::InsufficientLiquidity(message)
+            | CoreError::EmptyBook(message)
+            | CoreError::ArithmeticOverflow(message)
+            | CoreError::InvalidPrecision(message)
+            | CoreError::Io(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+impl From<std::io::Error> for CoreError {
+    fn from(error: std::io::Error) -> Self {
+        CoreError::Io(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CoreError {
+    fn from(error: serde_json::Error) -> Self {
+        if error.is_io() {
+            CoreError::Io(error.to_string())
+        } else {
+            CoreError::InvalidInput(error.to_string())
+        }
+    }
+}
+
+impl From<rust_decimal::Error> for CoreError {
+    fn from(error: rust_decimal::Error) -> Self {
+        match error {
+            rust_decimal::Error::ExceedsMaximumPossibleValue | rust_decimal::Error::LessThanMinimumPossibleValue => {
+                CoreError::ArithmeticOverflow(error.to_string())
+            }
+            rust_decimal::Error::ScaleExceedsMaximumPrecision(_) | rust_decimal::Error::Underflow => {
+                CoreError::InvalidPrecision(error.to_string())
+            }
+            _ => CoreError::InvalidInput(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_codes_and_conversions() {
+        let error = CoreError::MissingConfig("Leverage not set".to_string());
+        assert_eq!(error.code(), "MISSING_CONFIG");
+        assert_eq!(error.to_string(), "Leverage not set");
+
+        let error: CoreError = Decimal::from_str_exact("1.2.3").unwrap_err().into();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        let error: CoreError = Decimal::from_str_exact("1.00000000000000000000000000001").unwrap_err().into();
+        assert_eq!(error.code(), "INVALID_PRECISION");
+        let error: CoreError = serde_json::from_str::<u64>("x").unwrap_err().into();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+}
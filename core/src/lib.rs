@@ -1,5 +1,6 @@
 pub mod orderbook;
 pub mod compute;
+pub mod error;
 mod log;
 
 pub fn add(left: usize, right: usize) -> usize {
@@ -0,0 +1,164 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::OrderBook;
+
+/// Notional resting within `band` percent (1 = 1%) on each side of mid
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BandLiquidity {
+    pub band: Decimal,
+    pub bid_notional: Decimal,
+    pub ask_notional: Decimal,
+}
+
+// Every query below returns None when a side it needs is empty.
+// Ratios are rounded to 9 decimals like `compute_dry`
+impl OrderBook {
+    pub fn spread(&self) -> Option<Decimal> {
+        let (best_ask, best_bid) = self.best_ask_bid_pair()?;
+        Some(best_ask - best_bid)
+    }
+
+    /// Spread relative to mid, in basis points
+    pub fn spread_bps(&self) -> Option<Decimal> {
+        let spread = self.spread()?;
+        let mid_price = self.mid_price()?;
+        if mid_price.is_zero() {
+            return None;
+        }
+        Some((spread / mid_price * Decimal::from(10_000)).round_dp(9))
+    }
+
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let (best_ask, best_bid) = self.best_ask_bid_pair()?;
+        Some((best_ask + best_bid) / Decimal::TWO)
+    }
+
+    /// Mid weighted by the opposite top of book size, it leans towards the side with less size
+    pub fn microprice(&self) -> Option<Decimal> {
+        let (best_ask, best_bid) = self.best_ask_bid_pair()?;
+        let ask_size = self.asks[&best_ask];
+        let bid_size = self.bids[&best_bid];
+        let total_size = ask_size + bid_size;
+        if total_size.is_zero() {
+            return None;
+        }
+        Some(((best_ask * bid_size + best_bid * ask_size) / total_size).round_dp(9))
+    }
+
+    /// (bid volume - ask volume) / (bid volume + ask volume) over the best `levels` levels of each side,
+    /// from -1 (only asks) to 1 (only bids)
+    pub fn imbalance_top_n(&self, levels: usize) -> Option<Decimal> {
+        self.best_ask_bid_pair()?;
+        let bid_volume: Decimal = self.bids.values().rev().take(levels).sum();
+        let ask_volume: Decimal = self.asks.values().take(levels).sum();
+        imbalance(bid_volume, ask_volume)
+    }
+
+    /// Same as `imbalance_top_n` but over levels within `pct` percent (1 = 1%) of mid
+    pub fn imbalance_within_pct(&self, pct: Decimal) -> Option<Decimal> {
+        let (low, high) = self.band_around_mid(pct)?;
+        let bid_volume: Decimal = self.bids.range(low..).map(|(_, quantity)| quantity).sum();
+        let ask_volume: Decimal = self.asks.range(..=high).map(|(_, quantity)| quantity).sum();
+        imbalance(bid_volume, ask_volume)
+    }
+
+    /// Quote notional within each of `bands` percent (1 = 1%) around mid, in the order given
+    pub fn liquidity_within_bands(&self, bands: &[Decimal]) -> Option<Vec<BandLiquidity>> {
+        bands
+            .iter()
+            .map(|band| {
+                let (low, high) = self.band_around_mid(*band)?;
+                Some(BandLiquidity {
+                    band: *band,
+                    bid_notional: self.bids.range(low..).map(|(price, quantity)| price * quantity).sum(),
+                    ask_notional: self.asks.range(..=high).map(|(price, quantity)| price * quantity).sum(),
+                })
+            })
+            .collect()
+    }
+
+    fn best_ask_bid_pair(&self) -> Option<(Decimal, Decimal)> {
+        match self.get_best_ask_bid() {
+            (Some(best_ask), Some(best_bid)) => Some((best_ask, best_bid)),
+            _ => None,
+        }
+    }
+
+    fn band_around_mid(&self, pct: Decimal) -> Option<(Decimal, Decimal)> {
+        let mid_price = self.mid_price()?;
+        let band = mid_price * pct.max(Decimal::ZERO) / Decimal::ONE_HUNDRED;
+        Some((mid_price - band, mid_price + band))
+    }
+}
+
+fn imbalance(bid_volume: Decimal, ask_volume: Decimal) -> Option<Decimal> {
+    let total_volume = bid_volume + ask_volume;
+    if total_volume.is_zero() {
+        return None;
+    }
+    Some(((bid_volume - ask_volume) / total_volume).round_dp(9))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(2)), (dec!(110), dec!(4))],
+            vec![(dec!(99), dec!(3)), (dec!(98), dec!(1)), (dec!(90), dec!(5))],
+            None,
+        );
+        orderbook
+    }
+
+    #[test]
+    fn test_spread_and_mid() {
+        let orderbook = setup_order_book();
+        assert_eq!(orderbook.spread(), Some(dec!(2)));
+        assert_eq!(orderbook.mid_price(), Some(dec!(100)));
+        assert_eq!(orderbook.spread_bps(), Some(dec!(200)));
+    }
+
+    #[test]
+    fn test_microprice() {
+        // (101 * 3 + 99 * 1) / 4, pulled towards the thin ask
+        assert_eq!(setup_order_book().microprice(), Some(dec!(100.5)));
+    }
+
+    #[test]
+    fn test_imbalance() {
+        let orderbook = setup_order_book();
+        assert_eq!(orderbook.imbalance_top_n(1), Some(dec!(0.5)));
+        assert_eq!(orderbook.imbalance_top_n(2), Some(dec!(0.142857143)));
+        // 3% of 100 keeps 97..=103
+        assert_eq!(orderbook.imbalance_within_pct(dec!(3)), orderbook.imbalance_top_n(2));
+        assert_eq!(orderbook.imbalance_top_n(0), None);
+    }
+
+    #[test]
+    fn test_liquidity_within_bands() {
+        let bands = setup_order_book().liquidity_within_bands(&[dec!(1), dec!(5), dec!(10)]).unwrap();
+        assert_eq!(bands[0], BandLiquidity { band: dec!(1), bid_notional: dec!(297), ask_notional: dec!(101) });
+        assert_eq!(bands[1].bid_notional, dec!(395));
+        assert_eq!(bands[1].ask_notional, dec!(305));
+        assert_eq!(bands[2].bid_notional, dec!(845));
+        assert_eq!(bands[2].ask_notional, dec!(745));
+    }
+
+    #[test]
+    fn test_analytics_on_one_sided_book() {
+        let mut orderbook = OrderBook::new();
+        assert_eq!(orderbook.spread(), None);
+        orderbook.initialize(vec![(dec!(101), dec!(1))], vec![], None);
+        assert_eq!(orderbook.mid_price(), None);
+        assert_eq!(orderbook.spread_bps(), None);
+        assert_eq!(orderbook.microprice(), None);
+        assert_eq!(orderbook.imbalance_top_n(5), None);
+        assert_eq!(orderbook.imbalance_within_pct(dec!(5)), None);
+        assert_eq!(orderbook.liquidity_within_bands(&[dec!(1)]), None);
+    }
+}
@@ -0,0 +1,90 @@
+use super::OrderBook;
+
+/// Number of levels per side covered by the checksum unless the venue says otherwise
+pub const DEFAULT_CHECKSUM_DEPTH: usize = 25;
+
+impl OrderBook {
+    /// CRC32 over the top `depth` levels of both sides.
+    ///
+    /// Levels are interleaved best first as `bid_price:bid_qty:ask_price:ask_qty:...`,
+    /// when one side runs out the remaining levels of the other side are appended.
+    /// Prices and quantities are written with the decimals they are stored with: as received
+    /// without a market spec, padded to the market precision with one ("100.1" is written "100.10"
+    /// with a 0.01 tick). Venues checksum their wire format, so a spec must use the same
+    /// precisions as the venue's messages for checksums to match.
+    pub fn checksum(&self, depth: usize) -> u32 {
+        let mut bids = self.bids.iter().rev().take(depth);
+        let mut asks = self.asks.iter().take(depth);
+        let mut parts: Vec<String> = Vec::with_capacity(depth * 4);
+        loop {
+            let bid = bids.next();
+            let ask = asks.next();
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for (price, quantity) in bid.into_iter().chain(ask) {
+                parts.push(price.to_string());
+                parts.push(quantity.to_string());
+            }
+        }
+        crc32fast::hash(parts.join(":").as_bytes())
+    }
+
+    /// Compare the local book against the checksum published by the exchange
+    pub fn verify_checksum(&self, expected: u32, depth: usize) -> bool {
+        self.checksum(depth) == expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::MarketSpec;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_checksum_interleaves_levels() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100), dec!(2))], vec![(dec!(99), dec!(1))], None);
+        // crc32("99:1:100:2")
+        assert_eq!(order_book.checksum(DEFAULT_CHECKSUM_DEPTH), 4017713515);
+    }
+
+    #[test]
+    fn test_checksum_uneven_sides_and_depth() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(
+            vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(3))],
+            vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(2))],
+            None,
+        );
+        // crc32("0.9:1:1.0:1:0.8:2:1.1:3")
+        assert_eq!(order_book.checksum(25), 1666240158);
+        // crc32("0.9:1:1.0:1")
+        assert_eq!(order_book.checksum(1), 2745370730);
+
+        order_book.update_order(false, vec![(dec!(0.8), dec!(0))]).unwrap();
+        // crc32("0.9:1:1.0:1:1.1:3")
+        assert_eq!(order_book.checksum(25), 1788923572);
+    }
+
+    #[test]
+    fn test_checksum_with_market_spec() {
+        let mut order_book = OrderBook::new();
+        order_book.set_market_spec(MarketSpec::new(dec!(0.01), dec!(0.001)).unwrap());
+        order_book.initialize(vec![(dec!(100.1), dec!(2))], vec![(dec!(99), dec!(1))], None);
+        // crc32("99.00:1.000:100.10:2.000"), not the crc32("99:1:100.1:2") of the raw levels
+        assert_eq!(order_book.checksum(DEFAULT_CHECKSUM_DEPTH), 463806177);
+        assert!(!order_book.verify_checksum(crc32fast::hash(b"99:1:100.1:2"), DEFAULT_CHECKSUM_DEPTH));
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100), dec!(2))], vec![(dec!(99), dec!(1))], None);
+        assert!(order_book.verify_checksum(4017713515, DEFAULT_CHECKSUM_DEPTH));
+
+        order_book.update_order(true, vec![(dec!(100), dec!(3))]).unwrap();
+        assert!(!order_book.verify_checksum(4017713515, DEFAULT_CHECKSUM_DEPTH));
+    }
+}
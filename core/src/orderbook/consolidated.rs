@@ -0,0 +1,297 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::fill::walk_levels;
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+/// One venue feeding a `ConsolidatedBook`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSource {
+    pub id: String,
+    pub orderbook: OrderBook,
+    /// Taker fee rate of the venue, 0.0005 = 0.05%
+    pub taker_fee: Decimal,
+}
+
+impl BookSource {
+    /// Price a taker really pays (asks) or receives (bids) at `price` once the fee is included
+    pub fn effective_price(&self, price: Decimal, is_ask: bool) -> Decimal {
+        if is_ask {
+            price * (Decimal::ONE + self.taker_fee)
+        } else {
+            price * (Decimal::ONE - self.taker_fee)
+        }
+    }
+}
+
+/// Part of a consolidated level coming from one source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelSource {
+    pub source: String,
+    /// Price quoted by the source, before fees
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+/// Level of the consolidated book at a fee adjusted price
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsolidatedLevel {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    /// Where the quantity comes from, in source priority order
+    pub sources: Vec<LevelSource>,
+}
+
+/// Portion of a routed fill sent to one source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteLeg {
+    pub source: String,
+    pub base: Decimal,
+    /// Notional at the source prices, fees excluded
+    pub quote: Decimal,
+    pub fee: Decimal,
+    /// Average source price of the leg, fees excluded
+    pub avg_price: Decimal,
+}
+
+/// How a market order is best split across sources.
+///
+/// `avg_price`, `filled_quote` and `slippage` include fees; slippage is measured against the best
+/// fee adjusted price. When filling by quote the amount is what the taker spends or receives
+/// fees included. Values are rounded to 9 decimals like `compute_dry`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutedFill {
+    pub avg_price: Decimal,
+    pub filled_base: Decimal,
+    pub filled_quote: Decimal,
+    pub slippage: Decimal,
+    /// Legs by source priority, sources without a fill are left out
+    pub legs: Vec<RouteLeg>,
+    /// Whether all sources together had enough liquidity for the whole amount
+    pub is_complete: bool,
+}
+
+/// Several `OrderBook`s of the same pair merged into one book, each level keeping track of
+/// the sources it comes from. Prices are compared after the taker fee of each source.
+/// Sources added first win ties
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsolidatedBook {
+    sources: Vec<BookSource>,
+}
+
+// A source level at its fee adjusted price
+struct TaggedLevel {
+    effective_price: Decimal,
+    price: Decimal,
+    quantity: Decimal,
+    source: usize,
+}
+
+impl ConsolidatedBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_source(&mut self, id: &str, orderbook: OrderBook, taker_fee: Decimal) -> CoreResult<()> {
+        if self.source(id).is_some() {
+            return Err(CoreError::InvalidInput(format!("Source {} already exists", id)));
+        }
+        if taker_fee < Decimal::ZERO || taker_fee >= Decimal::ONE {
+            return Err(CoreError::InvalidInput(format!("Taker fee {} of source {} must be within [0, 1)", taker_fee, id)));
+        }
+        self.sources.push(BookSource { id: id.to_string(), orderbook, taker_fee });
+        Ok(())
+    }
+
+    pub fn remove_source(&mut self, id: &str) -> Option<BookSource> {
+        let index = self.sources.iter().position(|source| source.id == id)?;
+        Some(self.sources.remove(index))
+    }
+
+    pub fn source(&self, id: &str) -> Option<&BookSource> {
+        self.sources.iter().find(|source| source.id == id)
+    }
+
+    /// Mutable access to a source, e.g. to apply its updates
+    pub fn source_mut(&mut self, id: &str) -> Option<&mut BookSource> {
+        self.sources.iter_mut().find(|source| source.id == id)
+    }
+
+    pub fn sources(&self) -> &[BookSource] {
+        &self.sources
+    }
+
+    /// Merged asks and bids, best fee adjusted price first
+    pub fn get_depth(&self) -> (Vec<ConsolidatedLevel>, Vec<ConsolidatedLevel>) {
+        (self.merged_levels(true), self.merged_levels(false))
+    }
+
+    /// Best fee adjusted ask and bid across sources
+    pub fn get_best_ask_bid(&self) -> (Option<Decimal>, Option<Decimal>) {
+        let best = |is_ask: bool| self.tagged_levels(is_ask).first().map(|level| level.effective_price);
+        (best(true), best(false))
+    }
+
+    /// Route a market order of `fill_amount` across the sources, cheapest fee adjusted levels first.
+    /// Unlike `OrderBook::compute_dry` a partial fill is returned, see `is_complete`
+    pub fn compute_dry(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> RoutedFill {
+        let levels = self.tagged_levels(is_buy);
+        let estimate = walk_levels(
+            levels.iter().map(|level| (level.effective_price, level.quantity)),
+            fill_amount,
+            fill_by_quote,
+            is_buy,
+        );
+
+        let mut legs: Vec<(usize, Decimal, Decimal)> = vec![];
+        // fills follow the walked levels one to one
+        for (fill, level) in estimate.fills.iter().zip(levels.iter()) {
+            let quote = fill.base * level.price;
+            match legs.iter_mut().find(|(source, _, _)| *source == level.source) {
+                Some((_, base, leg_quote)) => {
+                    *base += fill.base;
+                    *leg_quote += quote;
+                }
+                None => legs.push((level.source, fill.base, quote)),
+            }
+        }
+        legs.sort_by_key(|(source, _, _)| *source);
+        let legs = legs
+            .into_iter()
+            .filter(|(_, base, _)| !base.is_zero())
+            .map(|(source, base, quote)| {
+                let source = &self.sources[source];
+                RouteLeg {
+                    source: source.id.clone(),
+                    base: base.round_dp(9),
+                    quote: quote.round_dp(9),
+                    fee: (quote * source.taker_fee).round_dp(9),
+                    avg_price: (quote / base).round_dp(9),
+                }
+            })
+            .collect();
+
+        RoutedFill {
+            avg_price: estimate.avg_price,
+            filled_base: estimate.filled_base,
+            filled_quote: estimate.filled_quote,
+            slippage: estimate.slippage,
+            legs,
+            is_complete: estimate.is_complete,
+        }
+    }
+
+    fn merged_levels(&self, is_ask: bool) -> Vec<ConsolidatedLevel> {
+        let mut merged: Vec<ConsolidatedLevel> = vec![];
+        for level in self.tagged_levels(is_ask) {
+            let source = LevelSource {
+                source: self.sources[level.source].id.clone(),
+                price: level.price,
+                quantity: level.quantity,
+            };
+            match merged.last_mut() {
+                Some(last) if last.price == level.effective_price => {
+                    last.quantity += level.quantity;
+                    last.sources.push(source);
+                }
+                _ => merged.push(ConsolidatedLevel {
+                    price: level.effective_price,
+                    quantity: level.quantity,
+                    sources: vec![source],
+                }),
+            }
+        }
+        merged
+    }
+
+    // Levels of every source, best effective price first, ties in source order
+    fn tagged_levels(&self, is_ask: bool) -> Vec<TaggedLevel> {
+        let mut levels: Vec<TaggedLevel> = self
+            .sources
+            .iter()
+            .enumerate()
+            .flat_map(|(index, source)| {
+                source.orderbook.taker_levels(is_ask).map(move |(price, quantity)| TaggedLevel {
+                    effective_price: source.effective_price(price, is_ask),
+                    price,
+                    quantity,
+                    source: index,
+                })
+            })
+            .collect();
+        // stable, so equal prices keep source order
+        if is_ask {
+            levels.sort_by_key(|level| level.effective_price);
+        } else {
+            levels.sort_by_key(|level| std::cmp::Reverse(level.effective_price));
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_consolidated_book() -> ConsolidatedBook {
+        let mut position = OrderBook::with_symbol("BTCUSD");
+        position.initialize(vec![(dec!(100), dec!(1)), (dec!(101), dec!(2))], vec![(dec!(99), dec!(1))], None);
+        let mut reference = OrderBook::with_symbol("BTCUSD");
+        reference.initialize(vec![(dec!(100), dec!(1)), (dec!(100.5), dec!(1))], vec![(dec!(99.5), dec!(2))], None);
+
+        let mut book = ConsolidatedBook::new();
+        book.add_source("position", position, dec!(0)).unwrap();
+        book.add_source("reference", reference, dec!(0.001)).unwrap();
+        book
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut book = setup_consolidated_book();
+        assert!(book.add_source("position", OrderBook::new(), dec!(0)).is_err());
+        assert!(book.add_source("other", OrderBook::new(), dec!(1)).is_err());
+        book.source_mut("position").unwrap().orderbook.update_order(true, vec![(dec!(100), dec!(0))]).unwrap();
+        assert_eq!(book.get_best_ask_bid(), (Some(dec!(100.1)), Some(dec!(99.4005))));
+        assert!(book.remove_source("reference").is_some());
+        assert_eq!(book.get_best_ask_bid(), (Some(dec!(101)), Some(dec!(99))));
+    }
+
+    #[test]
+    fn test_depth_provenance() {
+        let mut book = setup_consolidated_book();
+        book.source_mut("reference").unwrap().taker_fee = dec!(0);
+        let (asks, bids) = book.get_depth();
+        assert_eq!(asks[0].price, dec!(100));
+        assert_eq!(asks[0].quantity, dec!(2));
+        assert_eq!(
+            asks[0].sources.iter().map(|source| source.source.as_str()).collect::<Vec<_>>(),
+            vec!["position", "reference"]
+        );
+        assert_eq!(asks[1].sources[0], LevelSource { source: "reference".to_string(), price: dec!(100.5), quantity: dec!(1) });
+        assert_eq!(bids[0].sources[0].source, "reference");
+    }
+
+    #[test]
+    fn test_routed_fill() {
+        let book = setup_consolidated_book();
+        // 100 (position), 100.1 (reference 100 + fee), 100.6005 (reference 100.5 + fee), 101 (position)
+        let routed = book.compute_dry(dec!(3), false, true);
+        assert!(routed.is_complete);
+        assert_eq!(routed.filled_base, dec!(3));
+        assert_eq!(routed.avg_price, dec!(100.233500000));
+        assert_eq!(routed.legs.len(), 2);
+        assert_eq!(routed.legs[0], RouteLeg { source: "position".to_string(), base: dec!(1), quote: dec!(100), fee: dec!(0), avg_price: dec!(100) });
+        assert_eq!(routed.legs[1].source, "reference");
+        assert_eq!(routed.legs[1].base, dec!(2));
+        assert_eq!(routed.legs[1].quote, dec!(200.5));
+        assert_eq!(routed.legs[1].fee, dec!(0.2005));
+        assert_eq!(routed.legs[1].avg_price, dec!(100.25));
+
+        let routed = book.compute_dry(dec!(10), false, false);
+        assert!(!routed.is_complete);
+        assert_eq!(routed.filled_base, dec!(3));
+        assert_eq!(routed.legs[0].base, dec!(1));
+    }
+}
@@ -0,0 +1,123 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{OrderBook, PriceLevel};
+use crate::error::CoreResult;
+
+/// One step of a depth chart: a level and the running totals from the best price up to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthPoint {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub cumulative_base: Decimal,
+    pub cumulative_quote: Decimal,
+}
+
+/// Cumulative depth of both sides, best price first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthCurve {
+    pub asks: Vec<DepthPoint>,
+    pub bids: Vec<DepthPoint>,
+}
+
+impl OrderBook {
+    /// Cumulative base and quote volume per level for a depth chart.
+    ///
+    /// * `grouping_size`: bucket levels like `group_prices` first
+    /// * `window`: only keep levels within this percentage around mid (1 = 1%).
+    ///   When one side is empty its best price stands in for mid
+    ///
+    /// Fails like `group_prices` on a bad `grouping_size`
+    pub fn cumulative_depth(&self, grouping_size: Option<Decimal>, window: Option<Decimal>) -> CoreResult<DepthCurve> {
+        let (asks, bids) = match grouping_size {
+            Some(grouping_size) => self.group_prices(grouping_size)?,
+            None => self.get_depth(),
+        };
+
+        let (best_ask, best_bid) = self.get_best_ask_bid();
+        let center = match (best_ask, best_bid) {
+            (Some(best_ask), Some(best_bid)) => Some((best_ask + best_bid) / Decimal::TWO),
+            (best_ask, best_bid) => best_ask.or(best_bid),
+        };
+        let (low, high) = match (window, center) {
+            (Some(window), Some(center)) => {
+                let band = center * window / Decimal::ONE_HUNDRED;
+                (Some(center - band), Some(center + band))
+            }
+            _ => (None, None),
+        };
+
+        Ok(DepthCurve {
+            asks: accumulate(asks.into_iter().take_while(|(price, _)| !matches!(high, Some(high) if *price > high))),
+            bids: accumulate(bids.into_iter().take_while(|(price, _)| !matches!(low, Some(low) if *price < low))),
+        })
+    }
+}
+
+fn accumulate(levels: impl Iterator<Item = PriceLevel>) -> Vec<DepthPoint> {
+    let mut cumulative_base = Decimal::ZERO;
+    let mut cumulative_quote = Decimal::ZERO;
+    levels
+        .map(|(price, quantity)| {
+            cumulative_base += quantity;
+            cumulative_quote += price * quantity;
+            DepthPoint { price, quantity, cumulative_base, cumulative_quote }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(2)), (dec!(105), dec!(1)), (dec!(120), dec!(5))],
+            vec![(dec!(99), dec!(2)), (dec!(98.5), dec!(1)), (dec!(95), dec!(3)), (dec!(80), dec!(5))],
+            None,
+        );
+        orderbook
+    }
+
+    #[test]
+    fn test_cumulative_depth() {
+        let curve = setup_order_book().cumulative_depth(None, None).unwrap();
+        assert_eq!(curve.asks.len(), 4);
+        assert_eq!(
+            curve.asks[1],
+            DepthPoint { price: dec!(102), quantity: dec!(2), cumulative_base: dec!(3), cumulative_quote: dec!(305) }
+        );
+        assert_eq!(curve.bids[1].price, dec!(98.5));
+        assert_eq!(curve.bids[1].cumulative_base, dec!(3));
+        assert_eq!(curve.bids[1].cumulative_quote, dec!(296.5));
+        assert_eq!(curve.bids[3].cumulative_base, dec!(11));
+    }
+
+    #[test]
+    fn test_cumulative_depth_window() {
+        // mid 100, 5% keeps 95..=105
+        let curve = setup_order_book().cumulative_depth(None, Some(dec!(5))).unwrap();
+        assert_eq!(curve.asks.iter().map(|point| point.price).collect::<Vec<_>>(), vec![dec!(101), dec!(102), dec!(105)]);
+        assert_eq!(curve.bids.last().unwrap().price, dec!(95));
+        assert_eq!(curve.bids.last().unwrap().cumulative_base, dec!(6));
+    }
+
+    #[test]
+    fn test_cumulative_depth_grouped() {
+        let curve = setup_order_book().cumulative_depth(Some(dec!(10)), None).unwrap();
+        assert_eq!(curve.bids[0].price, dec!(90));
+        assert_eq!(curve.bids[0].cumulative_base, dec!(6));
+        assert_eq!(curve.bids[1].cumulative_base, dec!(11));
+    }
+
+    #[test]
+    fn test_cumulative_depth_one_sided() {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(vec![(dec!(100), dec!(1)), (dec!(110), dec!(1))], vec![], None);
+        let curve = orderbook.cumulative_depth(None, Some(dec!(5))).unwrap();
+        assert_eq!(curve.asks.len(), 1);
+        assert!(curve.bids.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::types::Side;
+use super::OrderBook;
+
+/// Change notifications recorded by an `OrderBook` once `set_record_events(true)` is called
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BookEvent {
+    LevelAdded { side: Side, price: Decimal, quantity: Decimal },
+    LevelChanged { side: Side, price: Decimal, old_quantity: Decimal, quantity: Decimal },
+    LevelRemoved { side: Side, price: Decimal, old_quantity: Decimal },
+    /// Top of book moved, emitted after the level events that caused it
+    BestBidAskChanged { best_ask: Option<Decimal>, best_bid: Option<Decimal> },
+    /// The whole book was replaced (`initialize`), no level events are emitted for it
+    SnapshotReset { sequence: Option<u64> },
+}
+
+impl OrderBook {
+    /// Start or stop recording `BookEvent`s. Stopping drops the events not drained yet
+    pub fn set_record_events(&mut self, enabled: bool) {
+        match (enabled, self.events.is_some()) {
+            (true, false) => self.events = Some(vec![]),
+            (false, true) => self.events = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_recording_events(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// Take the events recorded since the last call, oldest first
+    pub fn drain_events(&mut self) -> Vec<BookEvent> {
+        self.events.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub(crate) fn emit(&mut self, event: impl FnOnce() -> BookEvent) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(event());
+        }
+    }
+
+    pub(crate) fn emit_best_change(&mut self, before: (Option<Decimal>, Option<Decimal>)) {
+        let (best_ask, best_bid) = self.get_best_ask_bid();
+        if (best_ask, best_bid) != before {
+            self.emit(|| BookEvent::BestBidAskChanged { best_ask, best_bid });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::{CrossPolicy, OrderBookDelta};
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.set_record_events(true);
+        order_book.initialize(
+            vec![(dec!(100), dec!(1)), (dec!(101), dec!(2))],
+            vec![(dec!(99), dec!(1)), (dec!(98), dec!(2))],
+            Some(1),
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_snapshot_reset_events() {
+        let mut order_book = setup_order_book();
+        assert_eq!(
+            order_book.drain_events(),
+            vec![
+                BookEvent::SnapshotReset { sequence: Some(1) },
+                BookEvent::BestBidAskChanged { best_ask: Some(dec!(100)), best_bid: Some(dec!(99)) },
+            ]
+        );
+        assert!(order_book.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_level_events() {
+        let mut order_book = setup_order_book();
+        order_book.drain_events();
+
+        order_book.update_order(true, vec![(dec!(101), dec!(3)), (dec!(102), dec!(1)), (dec!(103), dec!(0))]).unwrap();
+        assert_eq!(
+            order_book.drain_events(),
+            vec![
+                BookEvent::LevelChanged { side: Side::Ask, price: dec!(101), old_quantity: dec!(2), quantity: dec!(3) },
+                BookEvent::LevelAdded { side: Side::Ask, price: dec!(102), quantity: dec!(1) },
+            ]
+        );
+
+        // unchanged quantities are not reported
+        order_book.update_order(false, vec![(dec!(98), dec!(2))]).unwrap();
+        assert!(order_book.drain_events().is_empty());
+
+        order_book.update_order(false, vec![(dec!(99), dec!(0))]).unwrap();
+        assert_eq!(
+            order_book.drain_events(),
+            vec![
+                BookEvent::LevelRemoved { side: Side::Bid, price: dec!(99), old_quantity: dec!(1) },
+                BookEvent::BestBidAskChanged { best_ask: Some(dec!(100)), best_bid: Some(dec!(98)) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delta_and_prune_events() {
+        let mut order_book = setup_order_book();
+        order_book.cross_policy = CrossPolicy::PruneStale;
+        order_book.drain_events();
+
+        order_book.apply_delta(OrderBookDelta::new(2, 2, vec![(dec!(100), dec!(0))], vec![]));
+        assert_eq!(order_book.drain_events().len(), 2);
+
+        order_book.update_order(false, vec![(dec!(101.5), dec!(1))]).unwrap();
+        assert_eq!(
+            order_book.drain_events(),
+            vec![
+                BookEvent::LevelAdded { side: Side::Bid, price: dec!(101.5), quantity: dec!(1) },
+                BookEvent::LevelRemoved { side: Side::Ask, price: dec!(101), old_quantity: dec!(2) },
+                BookEvent::BestBidAskChanged { best_ask: None, best_bid: Some(dec!(101.5)) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_off_by_default() {
+        let mut order_book = OrderBook::new();
+        order_book.update_order(true, vec![(dec!(100), dec!(1))]).unwrap();
+        assert!(!order_book.is_recording_events());
+        assert!(order_book.drain_events().is_empty());
+
+        let mut order_book = setup_order_book();
+        order_book.set_record_events(false);
+        assert!(order_book.drain_events().is_empty());
+    }
+}
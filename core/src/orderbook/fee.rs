@@ -0,0 +1,136 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+/// Taker fee charged on a fill, in quote
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeBreakdown {
+    /// Taker fee rate, 0.001 = 0.1%
+    pub rate: Decimal,
+    /// Fee charged on the notional
+    pub amount: Decimal,
+    /// How far the fee moves the price per base unit, `effective_price` against `avg_price`
+    pub price_impact: Decimal,
+}
+
+/// All-in result of a market order once the taker fee is included.
+///
+/// The fee is charged in quote: a buy pays `notional + fee`, a sell receives `notional - fee`.
+/// Values are rounded to 9 decimals like `compute_dry`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeInclusiveFill {
+    /// Average book price, fees excluded, as returned by `compute_dry`
+    pub avg_price: Decimal,
+    /// Average price including the fee, `net_quote / filled_base`
+    pub effective_price: Decimal,
+    /// Base received on a buy, base sold on a sell
+    pub filled_base: Decimal,
+    /// Quote at book prices, fees excluded
+    pub notional: Decimal,
+    /// Quote paid on a buy, quote received on a sell, fees included
+    pub net_quote: Decimal,
+    /// Slippage of the book price against the best price, in 100%: 10 = 10%
+    pub slippage: Decimal,
+    pub fee: FeeBreakdown,
+}
+
+impl OrderBook {
+    /// `compute_dry` with a taker fee of `taker_fee` (0.001 = 0.1%) included.
+    /// When filling by quote `fill_amount` is what the taker spends (buy) or receives (sell)
+    /// fees included, so a buy of 1000 never costs more than 1000.
+    /// Fails like `compute_dry` when the book cannot fill the whole amount
+    pub fn compute_dry_with_fee(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool, taker_fee: Decimal) -> CoreResult<FeeInclusiveFill> {
+        if taker_fee < Decimal::ZERO || taker_fee >= Decimal::ONE {
+            return Err(CoreError::InvalidInput(format!("Taker fee {} must be within [0, 1)", taker_fee)));
+        }
+        let fee_factor = if is_buy { Decimal::ONE + taker_fee } else { Decimal::ONE - taker_fee };
+        let book_amount = if fill_by_quote { fill_amount / fee_factor } else { fill_amount };
+
+        let estimate = self.compute_fill(book_amount, fill_by_quote, is_buy)?;
+        if !estimate.is_complete {
+            return Err(self.unfillable(fill_amount, is_buy));
+        }
+
+        let notional = estimate.filled_quote;
+        let fee = (notional * taker_fee).round_dp(9);
+        let net_quote = if is_buy { notional + fee } else { notional - fee };
+        let effective_price = (net_quote / estimate.filled_base).round_dp(9);
+        Ok(FeeInclusiveFill {
+            avg_price: estimate.avg_price,
+            effective_price,
+            filled_base: estimate.filled_base,
+            notional,
+            net_quote,
+            slippage: estimate.slippage,
+            fee: FeeBreakdown {
+                rate: taker_fee,
+                amount: fee,
+                price_impact: (effective_price - estimate.avg_price).abs(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(
+            vec![(dec!(100), dec!(1)), (dec!(110), dec!(1))],
+            vec![(dec!(90), dec!(1)), (dec!(80), dec!(1))],
+            None,
+        );
+        orderbook
+    }
+
+    #[test]
+    fn test_fee_by_base() {
+        let orderbook = setup_order_book();
+        let fill = orderbook.compute_dry_with_fee(dec!(2), false, true, dec!(0.001)).unwrap();
+        assert_eq!(fill.avg_price, dec!(105));
+        assert_eq!(fill.notional, dec!(210));
+        assert_eq!(fill.fee.amount, dec!(0.21));
+        assert_eq!(fill.net_quote, dec!(210.21));
+        assert_eq!(fill.effective_price, dec!(105.105));
+        assert_eq!(fill.fee.price_impact, dec!(0.105));
+
+        let fill = orderbook.compute_dry_with_fee(dec!(2), false, false, dec!(0.001)).unwrap();
+        assert_eq!(fill.net_quote, dec!(169.83));
+        assert_eq!(fill.effective_price, dec!(84.915));
+
+        // without a fee the numbers are the ones of compute_dry
+        let fill = orderbook.compute_dry_with_fee(dec!(1.5), false, true, dec!(0)).unwrap();
+        assert_eq!((fill.avg_price, fill.filled_base, fill.slippage), orderbook.compute_dry(dec!(1.5), false, true).unwrap());
+        assert_eq!(fill.effective_price, fill.avg_price);
+    }
+
+    #[test]
+    fn test_fee_by_quote_is_all_in() {
+        let orderbook = setup_order_book();
+        // 100.1 spent: 100 of notional and 0.1 of fee
+        let fill = orderbook.compute_dry_with_fee(dec!(100.1), true, true, dec!(0.001)).unwrap();
+        assert_eq!(fill.filled_base, dec!(1));
+        assert_eq!(fill.net_quote, dec!(100.1));
+
+        // 89.91 received: 90 of notional minus 0.09 of fee
+        let fill = orderbook.compute_dry_with_fee(dec!(89.91), true, false, dec!(0.001)).unwrap();
+        assert_eq!(fill.filled_base, dec!(1));
+        assert_eq!(fill.fee.amount, dec!(0.09));
+    }
+
+    #[test]
+    fn test_fee_partial_and_invalid() {
+        let orderbook = setup_order_book();
+        let error = orderbook.compute_dry_with_fee(dec!(3), false, true, dec!(0.001)).unwrap_err();
+        assert_eq!(error.code(), "INSUFFICIENT_LIQUIDITY");
+        let error = OrderBook::new().compute_dry_with_fee(dec!(1), false, true, dec!(0.001)).unwrap_err();
+        assert_eq!(error.code(), "EMPTY_BOOK");
+        assert!(orderbook.compute_dry_with_fee(dec!(1), false, true, dec!(1)).is_err());
+        assert!(orderbook.compute_dry_with_fee(dec!(1), false, true, dec!(-0.001)).is_err());
+    }
+}
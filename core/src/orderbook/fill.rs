@@ -0,0 +1,302 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+/// Portion of a fill taken from a single price level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelFill {
+    pub price: Decimal,
+    pub base: Decimal,
+    pub quote: Decimal,
+}
+
+/// Result of walking the book for a market order.
+///
+/// `avg_price`, `filled_base`, `filled_quote`, `unfilled` and `slippage` are rounded to 9 decimals
+/// like `compute_dry`, the per level breakdown is exact.
+/// `unfilled` is expressed in the unit of the requested amount (quote when filling by quote)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FillEstimate {
+    pub avg_price: Decimal,
+    pub filled_base: Decimal,
+    pub filled_quote: Decimal,
+    pub unfilled: Decimal,
+    /// Price of the last level touched, the furthest from the best price
+    pub worst_price: Option<Decimal>,
+    pub levels_consumed: usize,
+    /// Slippage of the average price against the best price, in 100%: 10 = 10%
+    pub slippage: Decimal,
+    pub fills: Vec<LevelFill>,
+    /// Whether the book had enough liquidity for the whole amount
+    pub is_complete: bool,
+}
+
+impl FillEstimate {
+    pub fn empty(fill_amount: Decimal) -> Self {
+        Self {
+            avg_price: Decimal::ZERO,
+            filled_base: Decimal::ZERO,
+            filled_quote: Decimal::ZERO,
+            unfilled: fill_amount.max(Decimal::ZERO),
+            worst_price: None,
+            levels_consumed: 0,
+            slippage: Decimal::ZERO,
+            fills: vec![],
+            is_complete: false,
+        }
+    }
+}
+
+/// Where a capped market order stops walking the book
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PriceCap {
+    /// Do not trade at prices worse than this one (marketable limit / IOC)
+    LimitPrice(Decimal),
+    /// Do not trade at a level further than this percentage away from the best price, in 100%: 1 = 1%.
+    /// This bounds the worst price, not the average: the reported `slippage` is usually lower.
+    /// Use `OrderBook::max_fill_for_slippage` to bound the average slippage instead
+    MaxPriceDeviation(Decimal),
+}
+
+/// Slippage of `avg_price` against `best_price`, in 100%: 10 = 10%
+pub(crate) fn slippage(avg_price: Decimal, best_price: Decimal, is_buy: bool) -> Decimal {
+    if is_buy {
+        ((avg_price - best_price) / best_price) * Decimal::new(100, 0)
+    } else {
+        ((best_price - avg_price) / best_price) * Decimal::new(100, 0)
+    }
+}
+
+/// Walk `levels` (best price first) until `fill_amount` is filled or the levels run out
+pub(crate) fn walk_levels<I>(levels: I, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> FillEstimate
+where
+    I: Iterator<Item = (Decimal, Decimal)>,
+{
+    if fill_amount <= Decimal::ZERO {
+        return FillEstimate::empty(fill_amount);
+    }
+
+    let mut remaining_amount = fill_amount;
+    let mut total_quote = Decimal::ZERO;
+    let mut total_base = Decimal::ZERO;
+    let mut best_price: Option<Decimal> = None;
+    let mut fills: Vec<LevelFill> = vec![];
+
+    for (price, quantity) in levels {
+        best_price.get_or_insert(price);
+        let available_amount = if fill_by_quote { quantity * price } else { quantity };
+        if remaining_amount >= available_amount {
+            remaining_amount -= available_amount;
+            total_quote += quantity * price;
+            total_base += quantity;
+            fills.push(LevelFill { price, base: quantity, quote: quantity * price });
+        } else {
+            let remaining_quantity = if fill_by_quote {
+                remaining_amount / price
+            } else {
+                remaining_amount
+            };
+            total_quote += remaining_quantity * price;
+            total_base += remaining_quantity;
+            fills.push(LevelFill { price, base: remaining_quantity, quote: remaining_quantity * price });
+            remaining_amount = Decimal::ZERO;
+            break;
+        }
+        if remaining_amount.is_zero() {
+            break;
+        }
+    }
+
+    let best_price = match best_price {
+        Some(best_price) if !total_base.is_zero() => best_price,
+        _ => return FillEstimate::empty(fill_amount),
+    };
+
+    let avg_price = total_quote / total_base;
+    let slippage = slippage(avg_price, best_price, is_buy);
+
+    FillEstimate {
+        avg_price: avg_price.round_dp(9),
+        filled_base: total_base.round_dp(9),
+        filled_quote: total_quote.round_dp(9),
+        unfilled: remaining_amount.round_dp(9),
+        worst_price: fills.last().map(|fill| fill.price),
+        levels_consumed: fills.len(),
+        slippage: slippage.round_dp(9),
+        fills,
+        is_complete: remaining_amount.is_zero(),
+    }
+}
+
+impl OrderBook {
+    /// Walk the book for a market order of `fill_amount` and report what fills, level by level.
+    /// Unlike `compute_dry` a partial fill is reported instead of failing.
+    /// A non positive amount is `InvalidInput`, a side without levels `EmptyBook`
+    pub fn compute_fill(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> CoreResult<FillEstimate> {
+        self.check_fillable(fill_amount, is_buy)?;
+        Ok(walk_levels(self.taker_levels(is_buy), fill_amount, fill_by_quote, is_buy))
+    }
+
+    /// Same as `compute_fill` but stops at `cap`.
+    /// Whatever lies beyond the cap is reported in `unfilled` and `is_complete` is false,
+    /// a cap that is not marketable returns an empty estimate. A non positive limit price or a
+    /// negative deviation is `InvalidInput`
+    pub fn compute_capped_fill(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool, cap: PriceCap) -> CoreResult<FillEstimate> {
+        self.check_fillable(fill_amount, is_buy)?;
+        let (best_ask, best_bid) = self.get_best_ask_bid();
+        let best_price = if is_buy { best_ask } else { best_bid };
+        let limit_price = match (cap, best_price) {
+            (PriceCap::LimitPrice(price), _) if price <= Decimal::ZERO => {
+                return Err(CoreError::InvalidInput(format!("Limit price must be positive, got {}", price)));
+            }
+            (PriceCap::MaxPriceDeviation(max_deviation), _) if max_deviation < Decimal::ZERO => {
+                return Err(CoreError::InvalidInput(format!("Price deviation must not be negative, got {}", max_deviation)));
+            }
+            (PriceCap::LimitPrice(price), _) => price,
+            (PriceCap::MaxPriceDeviation(_), None) => return Err(self.unfillable(fill_amount, is_buy)),
+            (PriceCap::MaxPriceDeviation(max_deviation), Some(best_price)) => {
+                let band = best_price * max_deviation / Decimal::new(100, 0);
+                if is_buy { best_price + band } else { best_price - band }
+            }
+        };
+
+        let levels = self
+            .taker_levels(is_buy)
+            .take_while(|(price, _)| if is_buy { *price <= limit_price } else { *price >= limit_price });
+        Ok(walk_levels(levels, fill_amount, fill_by_quote, is_buy))
+    }
+
+    // Nothing can be walked for a non positive amount or a side without levels
+    fn check_fillable(&self, fill_amount: Decimal, is_buy: bool) -> CoreResult<()> {
+        if fill_amount <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Fill amount must be positive, got {}", fill_amount)));
+        }
+        if self.taker_levels(is_buy).all(|(_, quantity)| quantity.is_zero()) {
+            return Err(self.unfillable(fill_amount, is_buy));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(
+            vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
+            vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
+        );
+        orderbook
+    }
+
+    #[test]
+    fn test_compute_fill_complete() {
+        let estimate = setup_order_book().compute_fill(dec!(2.5), false, true).unwrap();
+        assert!(estimate.is_complete);
+        assert_eq!(estimate.avg_price, dec!(1.08));
+        assert_eq!(estimate.filled_base, dec!(2.5));
+        assert_eq!(estimate.filled_quote, dec!(2.7));
+        assert_eq!(estimate.unfilled, dec!(0));
+        assert_eq!(estimate.worst_price, Some(dec!(1.2)));
+        assert_eq!(estimate.levels_consumed, 3);
+        assert_eq!(estimate.slippage, dec!(8));
+        assert_eq!(estimate.fills[2], LevelFill { price: dec!(1.2), base: dec!(0.5), quote: dec!(0.6) });
+    }
+
+    #[test]
+    fn test_compute_fill_partial_keeps_fillable_amount() {
+        let orderbook = setup_order_book();
+        let estimate = orderbook.compute_fill(dec!(5), false, false).unwrap();
+        assert!(!estimate.is_complete);
+        assert_eq!(estimate.filled_base, dec!(4));
+        assert_eq!(estimate.filled_quote, dec!(3.0));
+        assert_eq!(estimate.avg_price, dec!(0.75));
+        assert_eq!(estimate.unfilled, dec!(1));
+        assert_eq!(estimate.worst_price, Some(dec!(0.6)));
+        assert_eq!(estimate.levels_consumed, 4);
+
+        // compute_dry fails on partial fills
+        assert_eq!(orderbook.compute_dry(dec!(5), false, false).unwrap_err().code(), "INSUFFICIENT_LIQUIDITY");
+    }
+
+    #[test]
+    fn test_compute_fill_by_quote_remainder() {
+        let estimate = setup_order_book().compute_fill(dec!(10), true, true).unwrap();
+        assert!(!estimate.is_complete);
+        assert_eq!(estimate.filled_quote, dec!(4.6));
+        assert_eq!(estimate.unfilled, dec!(5.4));
+    }
+
+    #[test]
+    fn test_compute_capped_fill_limit_price() {
+        let orderbook = setup_order_book();
+        let estimate = orderbook.compute_capped_fill(dec!(3), false, true, PriceCap::LimitPrice(dec!(1.1))).unwrap();
+        assert!(!estimate.is_complete);
+        assert_eq!(estimate.filled_base, dec!(2));
+        assert_eq!(estimate.avg_price, dec!(1.05));
+        assert_eq!(estimate.unfilled, dec!(1));
+        assert_eq!(estimate.worst_price, Some(dec!(1.1)));
+
+        // enough liquidity before the cap
+        let estimate = orderbook.compute_capped_fill(dec!(1.5), false, false, PriceCap::LimitPrice(dec!(0.8))).unwrap();
+        assert!(estimate.is_complete);
+        assert_eq!(estimate.avg_price, dec!(0.866666667));
+
+        // limit not marketable
+        let estimate = orderbook.compute_capped_fill(dec!(1), false, true, PriceCap::LimitPrice(dec!(0.95))).unwrap();
+        assert_eq!(estimate, FillEstimate::empty(dec!(1)));
+    }
+
+    #[test]
+    fn test_compute_capped_fill_max_price_deviation() {
+        let orderbook = setup_order_book();
+        // 15% from 1.0 allows 1.0, 1.1
+        let estimate = orderbook.compute_capped_fill(dec!(3), true, true, PriceCap::MaxPriceDeviation(dec!(15))).unwrap();
+        assert_eq!(estimate.filled_quote, dec!(2.1));
+        assert_eq!(estimate.unfilled, dec!(0.9));
+        assert_eq!(estimate.levels_consumed, 2);
+
+        // 25% from 0.9 allows down to 0.675
+        let estimate = orderbook.compute_capped_fill(dec!(10), false, false, PriceCap::MaxPriceDeviation(dec!(25))).unwrap();
+        assert_eq!(estimate.filled_base, dec!(3));
+        assert_eq!(estimate.worst_price, Some(dec!(0.7)));
+
+        let error = OrderBook::new().compute_capped_fill(dec!(1), false, true, PriceCap::MaxPriceDeviation(dec!(1))).unwrap_err();
+        assert_eq!(error.code(), "EMPTY_BOOK");
+        let error = orderbook.compute_capped_fill(dec!(1), false, true, PriceCap::MaxPriceDeviation(dec!(-1))).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_max_price_deviation_is_not_average_slippage() {
+        let orderbook = setup_order_book();
+        // the worst level is 10% away but the average of 1.0 and 1.1 is only 5% away
+        let estimate = orderbook.compute_capped_fill(dec!(3), false, true, PriceCap::MaxPriceDeviation(dec!(10))).unwrap();
+        assert_eq!(estimate.worst_price, Some(dec!(1.1)));
+        assert_eq!(estimate.slippage, dec!(5));
+
+        // capping the average at 10% goes into the 1.2 level
+        let max_fill = orderbook.max_fill_for_slippage(dec!(10), false, true);
+        assert!(max_fill > estimate.filled_base);
+        let estimate = orderbook.compute_fill(max_fill, false, true).unwrap();
+        assert_eq!(estimate.slippage, dec!(10));
+        assert_eq!(estimate.worst_price, Some(dec!(1.2)));
+    }
+
+    #[test]
+    fn test_compute_fill_empty_book_and_bad_input() {
+        let error = OrderBook::new().compute_fill(dec!(1), false, true).unwrap_err();
+        assert_eq!(error.code(), "EMPTY_BOOK");
+
+        let error = setup_order_book().compute_fill(dec!(0), false, true).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        let error = setup_order_book().compute_capped_fill(dec!(1), false, true, PriceCap::LimitPrice(dec!(0))).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+    }
+}
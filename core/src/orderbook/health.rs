@@ -0,0 +1,127 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::OrderBook;
+
+/// Consistency of the top of book, re-evaluated after every update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BookHealth {
+    #[default]
+    Healthy,
+    /// Best bid equals best ask
+    Locked,
+    /// Best bid above best ask, usually a lost delta. Fill estimates are meaningless until resynced
+    Crossed,
+}
+
+impl BookHealth {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, BookHealth::Healthy)
+    }
+}
+
+/// What `update_order` does when an update leaves the book locked or crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CrossPolicy {
+    /// Keep the levels and report the state through `health()`, the caller is expected to resnapshot
+    #[default]
+    Flag,
+    /// Trust the side that was just updated and drop the opposite levels it runs through
+    PruneStale,
+}
+
+impl OrderBook {
+    pub fn health(&self) -> BookHealth {
+        self.health
+    }
+
+    /// Re-evaluate the top of book after `updated_side` (true for asks) changed.
+    /// With `CrossPolicy::PruneStale` the opposite side is pruned, `None` means both sides
+    /// changed at once (snapshot, delta) and there is no side to trust, so the state is only flagged
+    pub(crate) fn check_cross(&mut self, updated_side: Option<bool>) {
+        if let (CrossPolicy::PruneStale, Some(is_ask)) = (self.cross_policy, updated_side) {
+            self.prune_opposite(is_ask);
+        }
+        self.health = match self.get_best_ask_bid() {
+            (Some(best_ask), Some(best_bid)) if best_bid > best_ask => BookHealth::Crossed,
+            (Some(best_ask), Some(best_bid)) if best_bid == best_ask => BookHealth::Locked,
+            _ => BookHealth::Healthy,
+        };
+    }
+
+    fn prune_opposite(&mut self, is_ask: bool) {
+        let stale: Vec<Decimal> = if is_ask {
+            match self.asks.keys().next() {
+                // bids at or above the fresh best ask are stale
+                Some(best_ask) => self.bids.range(best_ask..).map(|(price, _)| *price).collect(),
+                None => vec![],
+            }
+        } else {
+            match self.bids.keys().next_back() {
+                Some(best_bid) => self.asks.range(..=best_bid).map(|(price, _)| *price).collect(),
+                None => vec![],
+            }
+        };
+        let removals = stale.into_iter().map(|price| (price, Decimal::ZERO)).collect();
+        self.apply_levels(!is_ask, removals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBookDelta;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book(cross_policy: CrossPolicy) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.cross_policy = cross_policy;
+        order_book.initialize(
+            vec![(dec!(100), dec!(1)), (dec!(101), dec!(2))],
+            vec![(dec!(99), dec!(1)), (dec!(98), dec!(2))],
+            None,
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_flag_locked_and_crossed() {
+        let mut order_book = setup_order_book(CrossPolicy::Flag);
+        assert_eq!(order_book.health(), BookHealth::Healthy);
+
+        order_book.update_order(false, vec![(dec!(100), dec!(1))]).unwrap();
+        assert_eq!(order_book.health(), BookHealth::Locked);
+
+        order_book.update_order(false, vec![(dec!(100.5), dec!(1))]).unwrap();
+        assert_eq!(order_book.health(), BookHealth::Crossed);
+        assert_eq!(order_book.bids.len(), 4);
+
+        // the missing removals arrive, the book heals itself
+        order_book.update_order(true, vec![(dec!(100), dec!(0)), (dec!(101), dec!(0)), (dec!(102), dec!(1))]).unwrap();
+        assert_eq!(order_book.health(), BookHealth::Healthy);
+    }
+
+    #[test]
+    fn test_prune_stale_side() {
+        let mut order_book = setup_order_book(CrossPolicy::PruneStale);
+        order_book.update_order(false, vec![(dec!(100.5), dec!(1))]).unwrap();
+        assert_eq!(order_book.health(), BookHealth::Healthy);
+        assert_eq!(order_book.get_best_ask_bid(), (Some(dec!(101)), Some(dec!(100.5))));
+
+        order_book.update_order(true, vec![(dec!(99), dec!(3))]).unwrap();
+        assert_eq!(order_book.get_best_ask_bid(), (Some(dec!(99)), Some(dec!(98))));
+        assert_eq!(order_book.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_and_delta_are_only_flagged() {
+        let mut order_book = OrderBook::new();
+        order_book.cross_policy = CrossPolicy::PruneStale;
+        order_book.initialize(vec![(dec!(100), dec!(1))], vec![(dec!(100), dec!(1))], Some(1));
+        assert_eq!(order_book.health(), BookHealth::Locked);
+
+        order_book.apply_delta(OrderBookDelta::new(2, 2, vec![(dec!(99), dec!(1))], vec![(dec!(100), dec!(0))]));
+        assert_eq!(order_book.health(), BookHealth::Healthy);
+        assert_eq!(order_book.asks.len(), 2);
+    }
+}
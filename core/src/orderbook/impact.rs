@@ -0,0 +1,150 @@
+use rust_decimal::Decimal;
+
+use super::market;
+use super::OrderBook;
+
+impl OrderBook {
+    /// Largest amount that can be filled before the slippage reported by `compute_dry` exceeds
+    /// `max_slippage` (in 100%: 1 = 1%). The amount is in base, or in quote when `fill_by_quote`.
+    ///
+    /// Rounded the way `compute_dry` rounds its result, a base amount down to the market lot size and
+    /// the average price bound inward to the market price precision, so filling the returned amount
+    /// never reports more than the requested slippage
+    pub fn max_fill_for_slippage(&self, max_slippage: Decimal, fill_by_quote: bool, is_buy: bool) -> Decimal {
+        let (best_ask, best_bid) = self.get_best_ask_bid();
+        let best_price = match if is_buy { best_ask } else { best_bid } {
+            Some(best_price) => best_price,
+            None => return Decimal::ZERO,
+        };
+        let band = best_price * max_slippage.max(Decimal::ZERO) / Decimal::new(100, 0);
+        // the average price, as rounded by `compute_dry`, must stay on the good side of target
+        let target = if is_buy { best_price + band } else { best_price - band };
+        let target = market::round_price_limit(self.market.as_ref(), target, is_buy);
+
+        let mut total_base = Decimal::ZERO;
+        let mut total_quote = Decimal::ZERO;
+        for (price, quantity) in self.taker_levels(is_buy) {
+            let within_target = if is_buy { price <= target } else { price >= target };
+            let take = if within_target {
+                quantity
+            } else {
+                // solve (total_quote + x * price) / (total_base + x) = target for x
+                let headroom = if is_buy {
+                    (target * total_base - total_quote) / (price - target)
+                } else {
+                    (total_quote - target * total_base) / (target - price)
+                };
+                headroom.max(Decimal::ZERO).min(quantity)
+            };
+            total_base += take;
+            total_quote += take * price;
+            if take < quantity {
+                break;
+            }
+        }
+
+        let amount = if fill_by_quote { total_quote } else { total_base };
+        market::round_fill_amount(self.market.as_ref(), amount, fill_by_quote)
+    }
+
+    /// Largest amount that can be filled without trading at a price worse than `price`.
+    /// The amount is in base, or in quote when `fill_by_quote`, rounded like `max_fill_for_slippage`
+    pub fn max_fill_before_price(&self, price: Decimal, fill_by_quote: bool, is_buy: bool) -> Decimal {
+        let amount: Decimal = self
+            .taker_levels(is_buy)
+            .take_while(|(level_price, _)| if is_buy { *level_price <= price } else { *level_price >= price })
+            .map(|(level_price, quantity)| if fill_by_quote { level_price * quantity } else { quantity })
+            .sum();
+        market::round_fill_amount(self.market.as_ref(), amount, fill_by_quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::MarketSpec;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(
+            vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
+            vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
+        );
+        orderbook
+    }
+
+    #[test]
+    fn test_max_fill_for_slippage_buy() {
+        let orderbook = setup_order_book();
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(5), false, true), dec!(2));
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(5), true, true), dec!(2.1));
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(10), false, true), dec!(3));
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(0), false, true), dec!(1));
+
+        // round trip through compute_dry
+        let (_, _, slippage) = orderbook.compute_dry(dec!(2), false, true).unwrap();
+        assert_eq!(slippage, dec!(5));
+    }
+
+    #[test]
+    fn test_max_fill_for_slippage_sell() {
+        let orderbook = setup_order_book();
+        let max_base = orderbook.max_fill_for_slippage(dec!(10), false, false);
+        assert_eq!(max_base, dec!(2.727272727));
+
+        let (_, filled, slippage) = orderbook.compute_dry(max_base, false, false).unwrap();
+        assert_eq!(filled, max_base);
+        assert!(slippage <= dec!(10));
+
+        // whole side within the limit
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(50), false, false), dec!(4));
+    }
+
+    #[test]
+    fn test_max_fill_before_price() {
+        let orderbook = setup_order_book();
+        assert_eq!(orderbook.max_fill_before_price(dec!(1.15), false, true), dec!(2));
+        assert_eq!(orderbook.max_fill_before_price(dec!(1.15), true, true), dec!(2.1));
+        assert_eq!(orderbook.max_fill_before_price(dec!(0.7), false, false), dec!(3));
+        assert_eq!(orderbook.max_fill_before_price(dec!(0.95), false, false), dec!(0));
+    }
+
+    #[test]
+    fn test_max_fill_round_trips_with_market_spec() {
+        let mut orderbook = OrderBook::new();
+        orderbook.set_market_spec(MarketSpec::new(dec!(0.05), dec!(0.001)).unwrap());
+        orderbook.initialize(
+            vec![(dec!(100.1), dec!(1)), (dec!(100.15), dec!(0.5)), (dec!(101), dec!(2))],
+            vec![(dec!(99.95), dec!(1.5)), (dec!(99.5), dec!(2))],
+            None,
+        );
+
+        for max_slippage in [dec!(0.01), dec!(0.02), dec!(0.3), dec!(0.45)] {
+            for is_buy in [true, false] {
+                let max_base = orderbook.max_fill_for_slippage(max_slippage, false, is_buy);
+                assert_eq!(max_base, orderbook.market_spec().unwrap().round_quantity_to_lot(max_base));
+                let (_, filled, slippage) = orderbook.compute_dry(max_base, false, is_buy).unwrap();
+                assert_eq!(filled, max_base);
+                assert!(slippage <= max_slippage, "{} over {} filling {}", slippage, max_slippage, max_base);
+
+                let max_quote = orderbook.max_fill_for_slippage(max_slippage, true, is_buy);
+                let (_, _, slippage) = orderbook.compute_dry(max_quote, true, is_buy).unwrap();
+                assert!(slippage <= max_slippage, "{} over {} filling {} quote", slippage, max_slippage, max_quote);
+            }
+        }
+
+        // 0.02% over 100.1 is 100.12002, the average may only reach 100.12 as compute_dry rounds it up:
+        // 1.5 up to 100.15 and 0.005681.. at 101, down to the 0.001 lot
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(0.02), false, true), dec!(1.505));
+        assert_eq!(orderbook.max_fill_before_price(dec!(100.12), false, true), dec!(1.000));
+    }
+
+    #[test]
+    fn test_inverse_queries_on_empty_book() {
+        let orderbook = OrderBook::new();
+        assert_eq!(orderbook.max_fill_for_slippage(dec!(5), false, true), dec!(0));
+        assert_eq!(orderbook.max_fill_before_price(dec!(100), true, false), dec!(0));
+    }
+}
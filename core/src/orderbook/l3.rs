@@ -0,0 +1,293 @@
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use super::types::Side;
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+pub type OrderId = u64;
+
+/// A resting order in the level-3 book
+#[derive(Debug, Clone, PartialEq)]
+pub struct L3Order {
+    pub id: OrderId,
+    pub side: Side,
+    pub price: Decimal,
+    pub size: Decimal,
+    /// Monotonic arrival counter, lower means earlier in the queue
+    pub arrival: u64,
+}
+
+/// Per-order events of a level-3 feed. Sizes are absolute except for `Fill`,
+/// which carries the executed amount
+#[derive(Debug, Clone, PartialEq)]
+pub enum L3Event {
+    Add { id: OrderId, side: Side, price: Decimal, size: Decimal },
+    Modify { id: OrderId, price: Decimal, size: Decimal },
+    Cancel { id: OrderId },
+    Fill { id: OrderId, size: Decimal },
+}
+
+/// Order book keeping every resting order with its queue position.
+///
+/// An aggregated level-2 `OrderBook` is maintained alongside, so `compute_dry`,
+/// `get_depth`, `group_prices` and friends work unchanged through `l2()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct L3OrderBook {
+    orders: HashMap<OrderId, L3Order>,
+    // order ids per price, front of the queue first
+    asks: BTreeMap<Decimal, VecDeque<OrderId>>,
+    bids: BTreeMap<Decimal, VecDeque<OrderId>>,
+    l2: OrderBook,
+    next_arrival: u64,
+}
+
+impl L3OrderBook {
+    pub fn new() -> Self {
+        Self {
+            orders: HashMap::new(),
+            asks: BTreeMap::new(),
+            bids: BTreeMap::new(),
+            l2: OrderBook::new(),
+            next_arrival: 0,
+        }
+    }
+
+    pub fn with_symbol(symbol: &str) -> Self {
+        Self {
+            l2: OrderBook::with_symbol(symbol),
+            ..L3OrderBook::new()
+        }
+    }
+
+    /// Aggregated level-2 view of the resting orders
+    pub fn l2(&self) -> &OrderBook {
+        &self.l2
+    }
+
+    pub fn get_order(&self, id: OrderId) -> Option<&L3Order> {
+        self.orders.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    /// Orders resting at `price`, in time priority
+    pub fn orders_at(&self, side: Side, price: Decimal) -> Vec<&L3Order> {
+        self.queues(side)
+            .get(&price)
+            .map(|queue| queue.iter().map(|id| &self.orders[id]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Position of an order in its price queue and the size resting ahead of it
+    pub fn queue_position(&self, id: OrderId) -> Option<(usize, Decimal)> {
+        let order = self.orders.get(&id)?;
+        let queue = self.queues(order.side).get(&order.price)?;
+        let position = queue.iter().position(|queued| *queued == id)?;
+        let size_ahead = queue.iter().take(position).map(|queued| self.orders[queued].size).sum();
+        Some((position, size_ahead))
+    }
+
+    pub fn apply(&mut self, event: L3Event) -> CoreResult<()> {
+        match event {
+            L3Event::Add { id, side, price, size } => self.add_order(id, side, price, size),
+            L3Event::Modify { id, price, size } => self.modify_order(id, price, size),
+            L3Event::Cancel { id } => self.cancel_order(id).map(|_| ()),
+            L3Event::Fill { id, size } => self.fill_order(id, size),
+        }
+    }
+
+    pub fn add_order(&mut self, id: OrderId, side: Side, price: Decimal, size: Decimal) -> CoreResult<()> {
+        if self.orders.contains_key(&id) {
+            return Err(CoreError::InvalidInput(format!("Order {} already exists", id)));
+        }
+        check_positive(price, "price")?;
+        check_positive(size, "size")?;
+        self.enqueue(L3Order { id, side, price, size, arrival: 0 });
+        Ok(())
+    }
+
+    /// Change price and/or size of a resting order.
+    /// Reducing the size keeps queue priority, moving the price or increasing the size sends the order to the back
+    pub fn modify_order(&mut self, id: OrderId, price: Decimal, size: Decimal) -> CoreResult<()> {
+        check_positive(price, "price")?;
+        check_positive(size, "size")?;
+        let order = self.orders.get_mut(&id).ok_or_else(|| CoreError::InvalidInput(format!("Order {} not found", id)))?;
+        if price == order.price && size <= order.size {
+            let (side, delta) = (order.side, size - order.size);
+            order.size = size;
+            self.adjust_level(side, price, delta);
+            return Ok(());
+        }
+        if let Some(mut order) = self.remove(id) {
+            order.price = price;
+            order.size = size;
+            self.enqueue(order);
+        }
+        Ok(())
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId) -> CoreResult<L3Order> {
+        self.remove(id).ok_or_else(|| CoreError::InvalidInput(format!("Order {} not found", id)))
+    }
+
+    /// Execute `size` against a resting order, the order is removed once fully filled
+    pub fn fill_order(&mut self, id: OrderId, size: Decimal) -> CoreResult<()> {
+        check_positive(size, "fill size")?;
+        let order = self.orders.get_mut(&id).ok_or_else(|| CoreError::InvalidInput(format!("Order {} not found", id)))?;
+        if size > order.size {
+            return Err(CoreError::InvalidInput(format!("Fill size {} exceeds remaining size {} of order {}", size, order.size, id)));
+        }
+        if size == order.size {
+            self.remove(id);
+        } else {
+            order.size -= size;
+            let (side, price) = (order.side, order.price);
+            self.adjust_level(side, price, -size);
+        }
+        Ok(())
+    }
+
+    fn queues(&self, side: Side) -> &BTreeMap<Decimal, VecDeque<OrderId>> {
+        match side {
+            Side::Ask => &self.asks,
+            Side::Bid => &self.bids,
+        }
+    }
+
+    fn enqueue(&mut self, mut order: L3Order) {
+        order.arrival = self.next_arrival;
+        self.next_arrival += 1;
+        let queues = match order.side {
+            Side::Ask => &mut self.asks,
+            Side::Bid => &mut self.bids,
+        };
+        queues.entry(order.price).or_default().push_back(order.id);
+        self.adjust_level(order.side, order.price, order.size);
+        self.orders.insert(order.id, order);
+    }
+
+    fn remove(&mut self, id: OrderId) -> Option<L3Order> {
+        let order = self.orders.remove(&id)?;
+        let queues = match order.side {
+            Side::Ask => &mut self.asks,
+            Side::Bid => &mut self.bids,
+        };
+        if let Some(queue) = queues.get_mut(&order.price) {
+            queue.retain(|queued| *queued != id);
+            if queue.is_empty() {
+                queues.remove(&order.price);
+            }
+        }
+        self.adjust_level(order.side, order.price, -order.size);
+        Some(order)
+    }
+
+    // keep the aggregated level in sync, a zero total removes the level.
+    // Prices and sizes are checked positive on the way in, so the total never goes negative
+    fn adjust_level(&mut self, side: Side, price: Decimal, delta: Decimal) {
+        let levels = if side.is_ask() { &self.l2.asks } else { &self.l2.bids };
+        let total = levels.get(&price).copied().unwrap_or(Decimal::ZERO) + delta;
+        self.l2.apply_update(side.is_ask(), vec![(price, total)]);
+    }
+}
+
+impl Default for L3OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check_positive(value: Decimal, name: &str) -> CoreResult<()> {
+    if value <= Decimal::ZERO {
+        return Err(CoreError::InvalidInput(format!("Order {} must be positive, got {}", name, value)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_l3_book() -> L3OrderBook {
+        let mut book = L3OrderBook::new();
+        book.add_order(1, Side::Ask, dec!(100), dec!(1)).unwrap();
+        book.add_order(2, Side::Ask, dec!(100), dec!(2)).unwrap();
+        book.add_order(3, Side::Ask, dec!(101), dec!(1)).unwrap();
+        book.add_order(4, Side::Bid, dec!(99), dec!(1.5)).unwrap();
+        book.add_order(5, Side::Bid, dec!(98), dec!(2)).unwrap();
+        book
+    }
+
+    #[test]
+    fn test_l3_aggregates_to_l2() {
+        let book = setup_l3_book();
+        let (asks, bids) = book.l2().get_depth();
+        assert_eq!(asks, vec![(dec!(100), dec!(3)), (dec!(101), dec!(1))]);
+        assert_eq!(bids, vec![(dec!(99), dec!(1.5)), (dec!(98), dec!(2))]);
+
+        let (avg_price, filled_qty, _) = book.l2().compute_dry(dec!(4), false, true).unwrap();
+        assert_eq!(avg_price, dec!(100.25));
+        assert_eq!(filled_qty, dec!(4));
+    }
+
+    #[test]
+    fn test_l3_queue_priority() {
+        let mut book = setup_l3_book();
+        assert_eq!(book.queue_position(2), Some((1, dec!(1))));
+
+        // reducing size keeps priority
+        book.modify_order(1, dec!(100), dec!(0.5)).unwrap();
+        assert_eq!(book.queue_position(1), Some((0, dec!(0))));
+        assert_eq!(book.l2().asks[&dec!(100)], dec!(2.5));
+
+        // increasing size loses it
+        book.modify_order(1, dec!(100), dec!(3)).unwrap();
+        assert_eq!(book.queue_position(1), Some((1, dec!(2))));
+        let ids: Vec<OrderId> = book.orders_at(Side::Ask, dec!(100)).iter().map(|order| order.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(book.l2().asks[&dec!(100)], dec!(5));
+    }
+
+    #[test]
+    fn test_l3_modify_price_moves_level() {
+        let mut book = setup_l3_book();
+        book.modify_order(2, dec!(101), dec!(2)).unwrap();
+        assert_eq!(book.l2().asks[&dec!(100)], dec!(1));
+        assert_eq!(book.l2().asks[&dec!(101)], dec!(3));
+        assert_eq!(book.queue_position(2), Some((1, dec!(1))));
+    }
+
+    #[test]
+    fn test_l3_cancel_and_fill() {
+        let mut book = setup_l3_book();
+        book.apply(L3Event::Cancel { id: 3 }).unwrap();
+        assert!(!book.l2().asks.contains_key(&dec!(101)));
+
+        book.apply(L3Event::Fill { id: 4, size: dec!(0.5) }).unwrap();
+        assert_eq!(book.get_order(4).unwrap().size, dec!(1));
+        assert_eq!(book.l2().bids[&dec!(99)], dec!(1));
+
+        book.apply(L3Event::Fill { id: 4, size: dec!(1) }).unwrap();
+        assert!(book.get_order(4).is_none());
+        assert_eq!(book.l2().get_best_ask_bid(), (Some(dec!(100)), Some(dec!(98))));
+        assert_eq!(book.len(), 3);
+    }
+
+    #[test]
+    fn test_l3_rejects_invalid_events() {
+        let mut book = setup_l3_book();
+        assert!(book.add_order(1, Side::Bid, dec!(90), dec!(1)).is_err());
+        assert!(book.add_order(9, Side::Bid, dec!(90), dec!(0)).is_err());
+        assert!(book.apply(L3Event::Cancel { id: 42 }).is_err());
+        assert!(book.apply(L3Event::Fill { id: 5, size: dec!(3) }).is_err());
+        assert_eq!(book.l2().bids[&dec!(98)], dec!(2));
+    }
+}
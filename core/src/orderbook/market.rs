@@ -0,0 +1,312 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use super::fill::slippage;
+use super::{OrderBook, PriceLevel};
+use crate::error::{CoreError, CoreResult};
+
+/// Trading increments of a market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketSpec {
+    /// Smallest price increment, every price is a multiple of it
+    pub tick_size: Decimal,
+    /// Smallest quantity increment, every quantity is a multiple of it
+    pub lot_size: Decimal,
+    /// Decimals prices are displayed with
+    pub price_precision: u32,
+    /// Decimals quantities are displayed with
+    pub quantity_precision: u32,
+}
+
+impl MarketSpec {
+    /// Spec with precisions taken from the tick and lot sizes (0.01 -> 2 decimals)
+    pub fn new(tick_size: Decimal, lot_size: Decimal) -> CoreResult<Self> {
+        let price_precision = tick_size.normalize().scale();
+        let quantity_precision = lot_size.normalize().scale();
+        Self::with_precision(tick_size, lot_size, price_precision, quantity_precision)
+    }
+
+    pub fn with_precision(
+        tick_size: Decimal,
+        lot_size: Decimal,
+        price_precision: u32,
+        quantity_precision: u32,
+    ) -> CoreResult<Self> {
+        if tick_size <= Decimal::ZERO || lot_size <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Tick size {} and lot size {} must be positive", tick_size, lot_size)));
+        }
+        if tick_size.normalize().scale() > price_precision {
+            return Err(CoreError::InvalidPrecision(format!("Tick size {} needs more than {} decimals", tick_size, price_precision)));
+        }
+        if lot_size.normalize().scale() > quantity_precision {
+            return Err(CoreError::InvalidPrecision(format!("Lot size {} needs more than {} decimals", lot_size, quantity_precision)));
+        }
+        Ok(Self { tick_size, lot_size, price_precision, quantity_precision })
+    }
+
+    /// Reject a level whose price is off the tick grid or whose quantity is off the lot grid.
+    /// A zero quantity (level removal) is valid
+    pub fn validate_level(&self, price: Decimal, quantity: Decimal) -> CoreResult<()> {
+        if price <= Decimal::ZERO || !(price % self.tick_size).is_zero() {
+            return Err(CoreError::InvalidInput(format!("Price {} is not a positive multiple of tick size {}", price, self.tick_size)));
+        }
+        if quantity < Decimal::ZERO || !(quantity % self.lot_size).is_zero() {
+            return Err(CoreError::InvalidInput(format!("Quantity {} is not a multiple of lot size {}", quantity, self.lot_size)));
+        }
+        Ok(())
+    }
+
+    /// Same value written with the market precision, so "100.1" and "100.10" print alike.
+    /// Only the scale changes: a value needing more decimals than the precision is kept as is,
+    /// never rounded, so an off-grid level keeps its exact price and quantity
+    pub fn normalize_level(&self, (price, quantity): PriceLevel) -> PriceLevel {
+        (self.normalize_price(price), self.normalize_quantity(quantity))
+    }
+
+    pub fn normalize_price(&self, price: Decimal) -> Decimal {
+        with_scale(price, self.price_precision)
+    }
+
+    pub fn normalize_quantity(&self, quantity: Decimal) -> Decimal {
+        with_scale(quantity, self.quantity_precision)
+    }
+
+    /// Snap a price onto the tick grid, up or down
+    pub fn round_price_to_tick(&self, price: Decimal, round_up: bool) -> Decimal {
+        let ticks = price / self.tick_size;
+        let ticks = if round_up { ticks.ceil() } else { ticks.floor() };
+        self.normalize_price(ticks * self.tick_size)
+    }
+
+    /// Largest lot multiple not above `quantity`
+    pub fn round_quantity_to_lot(&self, quantity: Decimal) -> Decimal {
+        self.normalize_quantity((quantity / self.lot_size).floor() * self.lot_size)
+    }
+
+    /// Bucket sizes of 1x, 10x and 100x the tick, for the UI grouping selector
+    pub fn grouping_presets(&self) -> Vec<Decimal> {
+        [1, 10, 100]
+            .into_iter()
+            .map(|multiple| (self.tick_size * Decimal::from(multiple)).normalize())
+            .collect()
+    }
+}
+
+// `value` written with `precision` decimals when that does not change it
+fn with_scale(value: Decimal, precision: u32) -> Decimal {
+    let mut value = if value.scale() > precision { value.normalize() } else { value };
+    if value.scale() <= precision {
+        value.rescale(precision);
+    }
+    value
+}
+
+impl OrderBook {
+    /// Attach a market spec, the levels already in the book are normalized to it
+    pub fn set_market_spec(&mut self, market: MarketSpec) {
+        self.asks = self.asks.iter().map(|level| market.normalize_level((*level.0, *level.1))).collect();
+        self.bids = self.bids.iter().map(|level| market.normalize_level((*level.0, *level.1))).collect();
+        self.market = Some(market);
+        self.prefix.clear();
+    }
+
+    pub fn market_spec(&self) -> Option<&MarketSpec> {
+        self.market.as_ref()
+    }
+
+    /// `initialize` that rejects the whole snapshot if one level is off the market grid
+    pub fn try_initialize(&mut self, asks: Vec<PriceLevel>, bids: Vec<PriceLevel>, sequence: Option<u64>) -> CoreResult<()> {
+        self.validate_levels(asks.iter().chain(bids.iter()))?;
+        self.initialize(asks, bids, sequence);
+        Ok(())
+    }
+
+    /// `update_order` that also rejects the whole batch if one level is off the market grid
+    pub fn try_update_order(&mut self, is_ask: bool, updates: Vec<PriceLevel>) -> CoreResult<()> {
+        self.validate_levels(updates.iter())?;
+        self.update_order(is_ask, updates)
+    }
+
+    /// Bucket sizes the UI can offer, empty without a market spec
+    pub fn grouping_presets(&self) -> Vec<Decimal> {
+        self.market.as_ref().map(MarketSpec::grouping_presets).unwrap_or_default()
+    }
+
+    fn validate_levels<'a>(&self, mut levels: impl Iterator<Item = &'a PriceLevel>) -> CoreResult<()> {
+        match &self.market {
+            Some(market) => levels.try_for_each(|(price, quantity)| market.validate_level(*price, *quantity)),
+            None => Ok(()),
+        }
+    }
+}
+
+// `compute_dry` result as reported with `market`: base down to the lot size, priced again with
+// `fill_base` (a walk by base) when that drops part of the fill, then the average price rounded.
+// The slippage is that of the rounded average against `best_price`, so the three values agree.
+// A fill below one lot cannot be placed and is reported as zeros
+pub(crate) fn round_dry(
+    market: Option<&MarketSpec>,
+    dry: (Decimal, Decimal, Decimal),
+    best_price: Decimal,
+    is_buy: bool,
+    fill_base: impl FnOnce(Decimal) -> (Decimal, Decimal, Decimal),
+) -> (Decimal, Decimal, Decimal) {
+    let market = match market {
+        Some(market) => market,
+        None => return dry,
+    };
+    let lot_base = market.round_quantity_to_lot(dry.1);
+    if lot_base.is_zero() {
+        return (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    }
+    let avg_price = if lot_base < dry.1 { fill_base(lot_base).0 } else { dry.0 };
+    let avg_price = round_avg_price(market, avg_price, is_buy);
+    (avg_price, lot_base, slippage(avg_price, best_price, is_buy).round_dp(9))
+}
+
+// Conservative rounding of an average fill price: a buy never looks cheaper, a sell never richer
+pub(crate) fn round_avg_price(market: &MarketSpec, avg_price: Decimal, is_buy: bool) -> Decimal {
+    let strategy = if is_buy { RoundingStrategy::AwayFromZero } else { RoundingStrategy::ToZero };
+    market.normalize_price(avg_price.round_dp_with_strategy(market.price_precision, strategy))
+}
+
+// Worst average price `round_dry` can report without passing `limit`: the limit rounded inward to
+// the decimals the average is reported with, the market price precision or the 9 of `compute_dry`
+pub(crate) fn round_price_limit(market: Option<&MarketSpec>, limit: Decimal, is_buy: bool) -> Decimal {
+    let precision = market.map_or(9, |market| market.price_precision);
+    let strategy = if is_buy { RoundingStrategy::ToZero } else { RoundingStrategy::AwayFromZero };
+    limit.round_dp_with_strategy(precision, strategy)
+}
+
+// Fill amount `round_dry` keeps whole: down to 9 decimals, and a base amount down to the lot size
+pub(crate) fn round_fill_amount(market: Option<&MarketSpec>, amount: Decimal, fill_by_quote: bool) -> Decimal {
+    let amount = amount.round_dp_with_strategy(9, RoundingStrategy::ToZero);
+    match market {
+        Some(market) if !fill_by_quote => market.round_quantity_to_lot(amount),
+        _ => amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::GroupingRounding;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.set_market_spec(MarketSpec::new(dec!(0.05), dec!(0.001)).unwrap());
+        order_book.initialize(
+            vec![(dec!(100.1), dec!(1)), (dec!(100.15), dec!(0.5)), (dec!(101), dec!(2))],
+            vec![(dec!(99.95), dec!(1.5)), (dec!(99.5), dec!(2))],
+            None,
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_market_spec_validation() {
+        assert!(MarketSpec::new(dec!(0), dec!(1)).is_err());
+        assert!(MarketSpec::with_precision(dec!(0.001), dec!(1), 2, 0).is_err());
+
+        let market = MarketSpec::new(dec!(0.05), dec!(0.001)).unwrap();
+        assert_eq!((market.price_precision, market.quantity_precision), (2, 3));
+        assert!(market.validate_level(dec!(100.15), dec!(1.234)).is_ok());
+        assert!(market.validate_level(dec!(100.15), dec!(0)).is_ok());
+        assert!(market.validate_level(dec!(100.12), dec!(1)).is_err());
+        assert!(market.validate_level(dec!(100.15), dec!(1.2345)).is_err());
+
+        let mut order_book = setup_order_book();
+        assert!(order_book.try_update_order(true, vec![(dec!(100.2), dec!(1)), (dec!(100.22), dec!(1))]).is_err());
+        // nothing of a rejected batch is applied
+        assert!(!order_book.asks.contains_key(&dec!(100.2)));
+        assert!(order_book.try_update_order(true, vec![(dec!(100.2), dec!(1))]).is_ok());
+        assert!(order_book.try_initialize(vec![(dec!(100.01), dec!(1))], vec![], None).is_err());
+    }
+
+    #[test]
+    fn test_levels_are_normalized() {
+        let mut order_book = setup_order_book();
+        order_book.update_order(true, vec![(dec!(100.10), dec!(3))]).unwrap();
+        let (asks, _) = order_book.get_depth();
+        assert_eq!(asks[0].0.to_string(), "100.10");
+        assert_eq!(asks[0].1.to_string(), "3.000");
+        assert_eq!(asks.len(), 3);
+    }
+
+    #[test]
+    fn test_off_grid_levels_are_kept_exact() {
+        let mut order_book = setup_order_book();
+        // a quantity below half a lot used to round to 0 and delete the level
+        order_book.update_order(true, vec![(dec!(100.1), dec!(0.0004))]).unwrap();
+        assert_eq!(order_book.asks.get(&dec!(100.1)), Some(&dec!(0.0004)));
+        order_book.update_order(true, vec![(dec!(100.123), dec!(1.00000))]).unwrap();
+        let (asks, _) = order_book.get_depth();
+        assert_eq!(asks[1].0.to_string(), "100.123");
+        assert_eq!(asks[1].1.to_string(), "1.000");
+
+        // prices sharing a tick once rounded stay separate levels when the spec is attached
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100.101), dec!(1)), (dec!(100.104), dec!(2)), (dec!(100.2), dec!(1))], vec![], None);
+        order_book.set_market_spec(MarketSpec::new(dec!(0.01), dec!(0.001)).unwrap());
+        let (asks, _) = order_book.get_depth();
+        assert_eq!(asks, vec![(dec!(100.101), dec!(1)), (dec!(100.104), dec!(2)), (dec!(100.2), dec!(1))]);
+        assert_eq!(asks[2].0.to_string(), "100.20");
+    }
+
+    #[test]
+    fn test_compute_dry_rounded_to_increments() {
+        let order_book = setup_order_book();
+        // 1 @ 100.10 + 0.5 @ 100.15 = 150.175 / 1.5 = 100.11666..
+        let (avg_price, filled_base, _) = order_book.compute_dry(dec!(1.5), false, true).unwrap();
+        assert_eq!(avg_price.to_string(), "100.12");
+        assert_eq!(filled_base.to_string(), "1.500");
+
+        let (avg_price, filled_base, _) = order_book.compute_dry(dec!(100), true, false).unwrap();
+        assert_eq!(avg_price.to_string(), "99.95");
+        assert_eq!(filled_base, dec!(1.000));
+    }
+
+    #[test]
+    fn test_slippage_of_the_rounded_average() {
+        let order_book = setup_order_book();
+        // 150.175 / 1.5 = 100.11666.. is off the tick, reported as 100.12 against a best ask of 100.10
+        let (avg_price, _, slippage) = order_book.compute_dry(dec!(1.5), false, true).unwrap();
+        assert_eq!(avg_price, dec!(100.12));
+        assert_eq!(slippage, dec!(0.019980020));
+        assert_ne!(slippage, order_book.compute_fill(dec!(1.5), false, true).unwrap().slippage);
+
+        // 99.95 * 1.5 + 99.5 * 0.25 = 174.8 / 1.75 = 99.8857.. sold, reported as 99.88
+        let (avg_price, _, slippage) = order_book.compute_dry(dec!(1.75), false, false).unwrap();
+        assert_eq!(avg_price, dec!(99.88));
+        assert_eq!(slippage, ((dec!(99.95) - dec!(99.88)) / dec!(99.95) * dec!(100)).round_dp(9));
+    }
+
+    #[test]
+    fn test_compute_dry_prices_the_lot_rounded_base() {
+        let order_book = setup_order_book();
+        // 100.175075 buys 1 @ 100.10 and 0.0005 @ 100.15: only 1.000 can be placed, all of it @ 100.10
+        let (avg_price, filled_base, slippage) = order_book.compute_dry(dec!(100.175075), true, true).unwrap();
+        assert_eq!((avg_price, filled_base, slippage), (dec!(100.10), dec!(1.000), dec!(0)));
+        assert_eq!(order_book.compute_dry(dec!(1.0005), false, true).unwrap(), (avg_price, filled_base, slippage));
+
+        // less than a lot
+        assert_eq!(order_book.compute_dry(dec!(0.0004), false, true).unwrap_err().code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn test_grouping_presets_and_tick_aligned_groups() {
+        let order_book = setup_order_book();
+        assert_eq!(order_book.grouping_presets(), vec![dec!(0.05), dec!(0.5), dec!(5)]);
+        assert!(OrderBook::new().grouping_presets().is_empty());
+
+        let (asks, bids) = order_book.group_prices(dec!(0.5)).unwrap();
+        assert_eq!(asks.iter().map(|(price, _)| price.to_string()).collect::<Vec<_>>(), vec!["100.50", "101.00"]);
+        assert_eq!(bids[0].0.to_string(), "99.50");
+        assert_eq!(bids[0].1.to_string(), "3.500");
+
+        // a bucket off the tick grid is widened to the next tick multiple
+        let (asks, _) = order_book.group_prices_with(dec!(0.07), GroupingRounding::Floor).unwrap();
+        assert_eq!(asks[0].0.to_string(), "100.10");
+    }
+}
@@ -1,11 +1,50 @@
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+pub mod types;
+pub mod sequence;
+pub mod checksum;
+pub mod snapshot;
+pub mod l3;
+pub mod fill;
+pub mod impact;
+pub mod depth;
+pub mod analytics;
+pub mod health;
+pub mod registry;
+pub mod events;
+pub mod market;
+pub mod prefix;
+pub mod replay;
+pub mod consolidated;
+pub mod synthetic;
+pub mod fee;
+
+pub use types::{GroupingRounding, Side};
+pub use sequence::{DeltaOutcome, OrderBookDelta};
+pub use checksum::DEFAULT_CHECKSUM_DEPTH;
+pub use snapshot::{OrderBookSnapshot, SNAPSHOT_VERSION};
+pub use l3::{L3Event, L3Order, L3OrderBook, OrderId};
+pub use fill::{FillEstimate, LevelFill, PriceCap};
+pub use depth::{DepthCurve, DepthPoint};
+pub use analytics::BandLiquidity;
+pub use health::{BookHealth, CrossPolicy};
+pub use registry::OrderBookRegistry;
+pub use events::BookEvent;
+pub use market::MarketSpec;
+pub use replay::{OrderBookRecorder, OrderBookReplayer, ReplayMessage, ReplayRecord};
+pub use consolidated::{BookSource, ConsolidatedBook, ConsolidatedLevel, LevelSource, RouteLeg, RoutedFill};
+pub use synthetic::{SyntheticBook, SyntheticLeg};
+pub use fee::{FeeBreakdown, FeeInclusiveFill};
+use prefix::PrefixCache;
+use crate::error::{CoreError, CoreResult};
+
 // use wasm_bindgen::prelude::*;
 
 
-type PriceLevel = (Decimal, Decimal);
+pub type PriceLevel = (Decimal, Decimal);
 //
 // #[wasm_bindgen]
 // extern "C" {
@@ -27,10 +66,29 @@ type PriceLevel = (Decimal, Decimal);
 //     }
 // }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderBook {
-    pub asks: BTreeMap<Decimal, Decimal>,
-    pub bids: BTreeMap<Decimal, Decimal>,
+    pub symbol: String,
+    // levels change only through `initialize` / `update_order` / `apply_delta`,
+    // which keep the `compute_dry` cache in step
+    asks: BTreeMap<Decimal, Decimal>,
+    bids: BTreeMap<Decimal, Decimal>,
+    /// Sequence (update id) of the last snapshot or delta applied, `None` when the book is unsequenced
+    pub sequence: Option<u64>,
+    /// What to do when an update leaves the book locked or crossed
+    #[serde(default)]
+    pub cross_policy: CrossPolicy,
+    /// Tick and lot sizes, set with `set_market_spec`. `None` accepts any price and quantity
+    #[serde(default)]
+    market: Option<MarketSpec>,
+    #[serde(default)]
+    health: BookHealth,
+    // pending change notifications, `None` while not recording
+    #[serde(skip)]
+    events: Option<Vec<BookEvent>>,
+    // cumulative totals behind `compute_dry`, trimmed by every level update
+    #[serde(skip)]
+    prefix: PrefixCache,
 }
 
 pub trait OrderbookLog {
@@ -46,90 +104,133 @@ impl OrderbookLog for OrderBook {
 impl OrderBook {
     pub fn new() -> Self {
         OrderBook {
+            symbol: String::new(),
             asks: BTreeMap::new(),
             bids: BTreeMap::new(),
+            sequence: None,
+            cross_policy: CrossPolicy::default(),
+            market: None,
+            health: BookHealth::default(),
+            events: None,
+            prefix: PrefixCache::default(),
+        }
+    }
+
+    pub fn with_symbol(symbol: &str) -> Self {
+        OrderBook {
+            symbol: symbol.to_string(),
+            ..OrderBook::new()
         }
     }
 
-    pub fn initialize(&mut self, asks: Vec<PriceLevel>, bids: Vec<PriceLevel>) {
+    /// Asks by price, best (lowest) first
+    pub fn asks(&self) -> &BTreeMap<Decimal, Decimal> {
+        &self.asks
+    }
+
+    /// Bids by price, best (highest) last
+    pub fn bids(&self) -> &BTreeMap<Decimal, Decimal> {
+        &self.bids
+    }
+
+    /// Replace the whole book with a snapshot.
+    /// `sequence` is the snapshot update id, deltas applied with `apply_delta` must continue from it
+    pub fn initialize(&mut self, asks: Vec<PriceLevel>, bids: Vec<PriceLevel>, sequence: Option<u64>) {
+        let best_before = self.get_best_ask_bid();
         // Clear the existing orderbook
         self.asks.clear();
         self.bids.clear();
-        for (price, quantity) in asks {
+        self.prefix.clear();
+        self.sequence = sequence;
+        for level in asks {
+            let (price, quantity) = self.normalize_level(level);
             self.asks.insert(price, quantity);
         }
-        for (price, quantity) in bids {
+        for level in bids {
+            let (price, quantity) = self.normalize_level(level);
             self.bids.insert(price, quantity);
         }
+        self.check_cross(None);
+        self.emit(|| BookEvent::SnapshotReset { sequence });
+        self.emit_best_change(best_before);
     }
 
 
-    pub fn update_order(&mut self, is_ask: bool, updates: Vec<PriceLevel>) {
-        let book = if is_ask { &mut self.asks } else { &mut self.bids };
-        for (price, quantity) in updates {
-            if quantity.is_zero() {
-                book.remove(&price);
-            } else {
-                if let Some(existing_quantity) = book.get_mut(&price) {
-                    *existing_quantity = quantity;
-                } else {
-                    book.insert(price, quantity);
-                }
-            }
+    /// Apply absolute level updates to one side, a zero quantity removes the level.
+    /// A level with a non positive price or a negative quantity rejects the whole batch with `InvalidInput`.
+    /// The book is then checked for a locked or crossed top, see `health()` and `cross_policy`
+    pub fn update_order(&mut self, is_ask: bool, updates: Vec<PriceLevel>) -> CoreResult<()> {
+        if let Some((price, quantity)) = updates.iter().find(|(price, quantity)| *price <= Decimal::ZERO || *quantity < Decimal::ZERO) {
+            return Err(CoreError::InvalidInput(format!("Level {} x {} needs a positive price and a non negative quantity", price, quantity)));
         }
+        self.apply_update(is_ask, updates);
+        Ok(())
     }
 
+    // `update_order` of levels already known to be valid
+    pub(crate) fn apply_update(&mut self, is_ask: bool, updates: Vec<PriceLevel>) {
+        let best_before = self.get_best_ask_bid();
+        self.apply_levels(is_ask, updates);
+        self.check_cross(Some(is_ask));
+        self.emit_best_change(best_before);
+    }
 
-    
-    pub fn compute_dry(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> (Decimal, Decimal, Decimal) {
-        // Select asks or bids based on the is_buy flag
-        let fill_map = if is_buy { &self.asks } else { &self.bids };
-        let mut remaining_amount = fill_amount;
-        let mut total_quote = Decimal::new(0, 0);
-        let mut total_base = Decimal::new(0, 0);
-        let iter: Box<dyn Iterator<Item = _>> = if is_buy {
-            Box::new(fill_map.iter())
-        } else {
-            // reverse the order list if it is a sell order
-            // because BTreeMap is sorted in ascending order
-            Box::new(fill_map.iter().rev())
-        };
-
-        for (price, quantity) in iter {
-            let available_amount = if fill_by_quote { *quantity * *price } else { *quantity };
-            if remaining_amount >= available_amount {
-                remaining_amount -= available_amount;
-                total_quote += *quantity * *price;
-                total_base += *quantity;
+    pub(crate) fn apply_levels(&mut self, is_ask: bool, updates: Vec<PriceLevel>) {
+        let side = if is_ask { Side::Ask } else { Side::Bid };
+        for level in updates {
+            let (price, quantity) = self.normalize_level(level);
+            self.prefix.invalidate(is_ask, price);
+            let book = if is_ask { &mut self.asks } else { &mut self.bids };
+            let old_quantity = if quantity.is_zero() {
+                book.remove(&price)
             } else {
-                let remaining_quantity = if fill_by_quote {
-                    remaining_amount / *price
-                } else {
-                    remaining_amount
-                };
-                total_quote += remaining_quantity * *price;
-                total_base += remaining_quantity;
-                remaining_amount = Decimal::ZERO;
-                break;
-            }
-            if remaining_amount.is_zero() {
-                break;
+                book.insert(price, quantity)
+            };
+            match old_quantity {
+                None if !quantity.is_zero() => self.emit(|| BookEvent::LevelAdded { side, price, quantity }),
+                Some(old_quantity) if quantity.is_zero() => {
+                    self.emit(|| BookEvent::LevelRemoved { side, price, old_quantity })
+                }
+                Some(old_quantity) if old_quantity != quantity => {
+                    self.emit(|| BookEvent::LevelChanged { side, price, old_quantity, quantity })
+                }
+                _ => {}
             }
         }
+    }
+
 
-        if !remaining_amount.is_zero() || total_base.is_zero() {
-            return (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+    fn normalize_level(&self, level: PriceLevel) -> PriceLevel {
+        match &self.market {
+            Some(market) => market.normalize_level(level),
+            None => level,
         }
+    }
 
-        let avg_price = total_quote / total_base;
-        let (best_ask, best_bid) = self.get_best_ask_bid();
-        let slippage = if is_buy {
-            ((avg_price - best_ask.unwrap()) / best_ask.unwrap()) * Decimal::new(100, 0)
-        } else {
-            ((best_bid.unwrap() - avg_price) / best_bid.unwrap()) * Decimal::new(100, 0)
-        };
+    /// Compute the average price, total base filled and slippage of a market order.
+    /// Fails with `EmptyBook` when the side has no level and `InsufficientLiquidity` when it cannot
+    /// fill the whole amount, use `compute_fill` for the partial fill details.
+    /// With a market spec the base is rounded down to the lot size, the average price is the one of
+    /// that base rounded against the taker to the price precision, and a fill below one lot is `InvalidInput`.
+    /// Cumulative totals are cached between calls, repeated quotes take a binary search
+    pub fn compute_dry(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> CoreResult<(Decimal, Decimal, Decimal)> {
+        self.prefix_dry(fill_amount, fill_by_quote, is_buy)
+    }
 
-        (avg_price.round_dp(9), total_base.round_dp(9), slippage.round_dp(9))
+    /// `compute_dry` returning zeros instead of the error, the tuple of the former API
+    #[deprecated(note = "use `compute_dry`, which reports why the book cannot fill the amount")]
+    pub fn compute_dry_or_zeros(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> (Decimal, Decimal, Decimal) {
+        self.compute_dry(fill_amount, fill_by_quote, is_buy).unwrap_or_default()
+    }
+
+    // Why a market order of `fill_amount` cannot be filled
+    pub(crate) fn unfillable(&self, fill_amount: Decimal, is_buy: bool) -> CoreError {
+        let side = if is_buy { "asks" } else { "bids" };
+        if self.taker_levels(is_buy).all(|(_, quantity)| quantity.is_zero()) {
+            CoreError::EmptyBook(format!("Order book {} has no {}", self.symbol, side))
+        } else {
+            CoreError::InsufficientLiquidity(format!("Not enough {} in order book {} to fill {}", side, self.symbol, fill_amount))
+        }
     }
 
      pub fn get_depth(&self) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
@@ -138,28 +239,60 @@ impl OrderBook {
         bids.reverse();
         (asks, bids)
     }
+    // Levels a taker order walks through, best price first:
+    // asks ascending for a buy, bids descending for a sell
+    pub(crate) fn taker_levels(&self, is_buy: bool) -> Box<dyn Iterator<Item = PriceLevel> + '_> {
+        if is_buy {
+            Box::new(self.asks.iter().map(|(price, quantity)| (*price, *quantity)))
+        } else {
+            Box::new(self.bids.iter().rev().map(|(price, quantity)| (*price, *quantity)))
+        }
+    }
+
     pub fn get_best_ask_bid(&self) -> (Option<Decimal>, Option<Decimal>) {
         let best_ask = self.asks.iter().next().map(|(price, _)| *price);
         let best_bid = self.bids.iter().next_back().map(|(price, _)| *price);
         (best_ask, best_bid)
     }
 
-    pub fn group_prices(&self, grouping_size: Decimal) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
-        let group = |prices: &BTreeMap<Decimal, Decimal>| -> Vec<PriceLevel> {
+    /// Group levels into buckets of `grouping_size`, asks rounded up and bids rounded down.
+    /// See `group_prices_with` for other rounding modes
+    pub fn group_prices(&self, grouping_size: Decimal) -> CoreResult<(Vec<PriceLevel>, Vec<PriceLevel>)> {
+        self.group_prices_with(grouping_size, GroupingRounding::Conservative)
+    }
+
+    /// Group levels into buckets of `grouping_size`, any positive size works (0.25, 5, ...).
+    /// Only `GroupingRounding::Conservative` guarantees grouped asks stay above grouped bids
+    /// when the bucket is wider than the spread. A zero size returns the ungrouped depth,
+    /// a negative one is `InvalidInput`.
+    /// With a market spec the size is widened to a multiple of the tick, see `grouping_presets`
+    pub fn group_prices_with(&self, grouping_size: Decimal, rounding: GroupingRounding) -> CoreResult<(Vec<PriceLevel>, Vec<PriceLevel>)> {
+        if grouping_size < Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Grouping size must not be negative, got {}", grouping_size)));
+        }
+        if grouping_size.is_zero() {
+            return Ok(self.get_depth());
+        }
+        let grouping_size = match &self.market {
+            Some(market) => market.round_price_to_tick(grouping_size, true),
+            None => grouping_size,
+        };
+
+        let group = |prices: &BTreeMap<Decimal, Decimal>, is_ask: bool| -> CoreResult<Vec<PriceLevel>> {
             let mut grouped_prices = BTreeMap::new();
             for (price, quantity) in prices {
-                let grouped_price = (price / grouping_size).floor() * grouping_size;
+                let grouped_price = rounding.bucket(*price, grouping_size, is_ask)?;
                 let grouped_quantity = grouped_prices.entry(grouped_price).or_insert(dec!(0.0));
                 *grouped_quantity += quantity;
             }
-            grouped_prices.into_iter().collect()
+            Ok(grouped_prices.into_iter().map(|level| self.normalize_level(level)).collect())
         };
 
-        let grouped_asks = group(&self.asks);
-        let mut grouped_bids = group(&self.bids);
+        let grouped_asks = group(&self.asks, true)?;
+        let mut grouped_bids = group(&self.bids, false)?;
         grouped_bids.reverse();
 
-        (grouped_asks, grouped_bids)
+        Ok((grouped_asks, grouped_bids))
     }
 }
 
@@ -186,7 +319,7 @@ mod tests {
             (Decimal::from(99), Decimal::from(8)),
             (Decimal::from(98), Decimal::from(20)),
         ];
-        order_book.initialize(asks.clone(), bids.clone());
+        order_book.initialize(asks.clone(), bids.clone(), None);
 
         assert_eq!(order_book.asks.len(), 2);
         assert_eq!(order_book.bids.len(), 2);
@@ -203,15 +336,27 @@ mod tests {
             (Decimal::from(99), Decimal::from(8)),
             (Decimal::from(98), Decimal::from(20)),
         ];
-        order_book.initialize(asks.clone(), bids.clone());
+        order_book.initialize(asks.clone(), bids.clone(), None);
 
-        order_book.update_order(true, vec![(Decimal::from(100), Decimal::from(15))]);
+        order_book.update_order(true, vec![(Decimal::from(100), Decimal::from(15))]).unwrap();
         assert_eq!(order_book.asks[&Decimal::from(100)], Decimal::from(15));
 
-        order_book.update_order(false, vec![(Decimal::from(98), Decimal::from(0))]);
+        order_book.update_order(false, vec![(Decimal::from(98), Decimal::from(0))]).unwrap();
         assert!(!order_book.bids.contains_key(&Decimal::from(98)));
     }
 
+    #[test]
+    fn test_update_order_rejects_invalid_levels() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100), dec!(10))], vec![(dec!(99), dec!(8))], None);
+
+        let error = order_book.update_order(true, vec![(dec!(101), dec!(1)), (dec!(102), dec!(-1))]).unwrap_err();
+        assert_eq!(error.code(), "INVALID_INPUT");
+        // nothing of a rejected batch is applied
+        assert!(!order_book.asks.contains_key(&dec!(101)));
+        assert!(order_book.update_order(false, vec![(dec!(0), dec!(1))]).is_err());
+    }
+
     #[test]
     fn test_order_book_get_depth() {
         let mut order_book = OrderBook::new();
@@ -223,7 +368,7 @@ mod tests {
             (Decimal::from(99), Decimal::from(8)),
             (Decimal::from(98), Decimal::from(20)),
         ];
-        order_book.initialize(asks.clone(), bids.clone());
+        order_book.initialize(asks.clone(), bids.clone(), None);
 
         let (depth_asks, depth_bids) = order_book.get_depth();
         assert_eq!(depth_asks, vec![
@@ -243,6 +388,7 @@ mod tests {
         orderbook.initialize(
             vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
             vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
         );
 
         let (best_ask, best_bid) = orderbook.get_best_ask_bid();
@@ -261,9 +407,10 @@ mod tests {
                 (dec!(1.02), dec!(2.0)),
             ],
             vec![],
+            None,
         );
 
-        let (grouped_asks, grouped_bids) = orderbook.group_prices(dec!(0.01));
+        let (grouped_asks, grouped_bids) = orderbook.group_prices_with(dec!(0.01), GroupingRounding::Floor).unwrap();
 
         assert_eq!(
             grouped_asks,
@@ -292,13 +439,13 @@ mod tests {
             (dec!(70.000), dec!(1.0)),
         ];
         let mut order_book = OrderBook::new();
-        order_book.initialize(asks.clone(), bids.clone());
+        order_book.initialize(asks.clone(), bids.clone(), None);
         
-        // let (grouped_asks_0_001, grouped_bids_0_001) = order_book.group_prices(dec!(0.001));
+        // let (grouped_asks_0_001, grouped_bids_0_001) = order_book.group_prices(dec!(0.001)).unwrap();
         // assert_eq!(grouped_asks_0_001, asks);
         // assert_eq!(grouped_bids_0_001, bids);
 
-        let (grouped_asks_0_1, grouped_bids_0_1) = order_book.group_prices(dec!(0.1));
+        let (grouped_asks_0_1, grouped_bids_0_1) = order_book.group_prices_with(dec!(0.1), GroupingRounding::Floor).unwrap();
         let expected_asks_0_1 = vec![
             (dec!(100.0), dec!(2.0)),
             (dec!(110.0), dec!(3.0)),
@@ -314,7 +461,7 @@ mod tests {
         assert_eq!(grouped_asks_0_1, expected_asks_0_1);
         assert_eq!(grouped_bids_0_1, expected_bids_0_1);
 
-        let (grouped_asks_1, grouped_bids_1) = order_book.group_prices(dec!(1.0));
+        let (grouped_asks_1, grouped_bids_1) = order_book.group_prices_with(dec!(1.0), GroupingRounding::Floor).unwrap();
         let expected_asks_1 = vec![
             (dec!(100.0), dec!(2.0)),
             (dec!(110.0), dec!(3.0)),
@@ -329,7 +476,7 @@ mod tests {
         assert_eq!(grouped_asks_1, expected_asks_1);
         assert_eq!(grouped_bids_1, expected_bids_1);
 
-        let (grouped_asks_10, grouped_bids_10) = order_book.group_prices(dec!(10.0));
+        let (grouped_asks_10, grouped_bids_10) = order_book.group_prices_with(dec!(10.0), GroupingRounding::Floor).unwrap();
         let expected_asks_10 = vec![
             (dec!(100.0), dec!(2.0)),
             (dec!(110.0), dec!(3.0)),
@@ -344,6 +491,42 @@ mod tests {
         assert_eq!(grouped_bids_10, expected_bids_10);
     }
 
+    #[test]
+    fn test_group_prices_conservative() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(
+            vec![(dec!(100.3), dec!(1.0)), (dec!(100.6), dec!(1.0)), (dec!(101.1), dec!(2.0))],
+            vec![(dec!(100.1), dec!(1.0)), (dec!(99.8), dec!(1.0)), (dec!(99.2), dec!(3.0))],
+            None,
+        );
+
+        let (grouped_asks, grouped_bids) = order_book.group_prices(dec!(0.25)).unwrap();
+        assert_eq!(grouped_asks, vec![(dec!(100.50), dec!(1.0)), (dec!(100.75), dec!(1.0)), (dec!(101.25), dec!(2.0))]);
+        assert_eq!(grouped_bids, vec![(dec!(100.00), dec!(1.0)), (dec!(99.75), dec!(1.0)), (dec!(99.00), dec!(3.0))]);
+
+        // bucket wider than the spread: grouped sides must not touch
+        let (grouped_asks, grouped_bids) = order_book.group_prices(dec!(5)).unwrap();
+        assert_eq!(grouped_asks, vec![(dec!(105), dec!(4.0))]);
+        assert_eq!(grouped_bids, vec![(dec!(100), dec!(1.0)), (dec!(95), dec!(4.0))]);
+        assert!(grouped_asks[0].0 > grouped_bids[0].0);
+
+        // floor lets the grouped book cross, conservative never does
+        let (floor_asks, floor_bids) = order_book.group_prices_with(dec!(5), GroupingRounding::Floor).unwrap();
+        assert_eq!(floor_asks[0].0, floor_bids[0].0);
+        let (ceil_asks, _) = order_book.group_prices_with(dec!(0.25), GroupingRounding::Ceil).unwrap();
+        assert_eq!(ceil_asks[0], (dec!(100.50), dec!(1.0)));
+    }
+
+    #[test]
+    fn test_group_prices_invalid_size() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100.3), dec!(1.0))], vec![(dec!(99.2), dec!(3.0))], None);
+        assert_eq!(order_book.group_prices(dec!(0)).unwrap(), order_book.get_depth());
+        assert_eq!(order_book.group_prices(dec!(-1)).unwrap_err().code(), "INVALID_INPUT");
+        let error = order_book.group_prices(Decimal::new(1, 28)).unwrap_err();
+        assert_eq!(error.code(), "ARITHMETIC_OVERFLOW");
+    }
+
 }
 
 
@@ -358,9 +541,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
             vec![],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(4), false, true);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(4), false, true).unwrap();
         assert_eq!(avg_price, dec!(1.15));
         assert_eq!(filled_qty, dec!(4.0));
         assert_eq!(slippage, dec!(15));
@@ -372,9 +556,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
             vec![],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(2), false, true);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(2), false, true).unwrap();
         assert_eq!(avg_price, dec!(1.05));
         assert_eq!(filled_qty, dec!(2.0));
         assert_eq!(slippage, dec!(5));
@@ -386,9 +571,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
             vec![],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(3), true, true);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(3), true, true).unwrap();
         assert_eq!(avg_price, dec!(1.090909091));
         assert_eq!(filled_qty, dec!(2.75));
         assert_eq!(slippage, dec!(9.090909091));
@@ -400,9 +586,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![(dec!(1.0), dec!(1)), (dec!(1.1), dec!(1)), (dec!(1.2), dec!(1)), (dec!(1.3), dec!(1))],
             vec![],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(1.5), true, true);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(1.5), true, true).unwrap();
         assert_eq!(avg_price, dec!(1.03125));
         assert_eq!(filled_qty, dec!(1.454545455));
         assert_eq!(slippage, dec!(3.125));
@@ -414,9 +601,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![],
             vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(4), false, false);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(4), false, false).unwrap();
         assert_eq!(avg_price, dec!(0.75));
         assert_eq!(filled_qty, dec!(4.0));
         assert_eq!(slippage, dec!(16.666666667));
@@ -428,9 +616,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![],
             vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(2), false, false);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(2), false, false).unwrap();
         assert_eq!(avg_price, dec!(0.85));
         assert_eq!(filled_qty, dec!(2.0));
         assert_eq!(slippage, dec!(5.555555556));
@@ -442,9 +631,10 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![],
             vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(3), true, false);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(3), true, false).unwrap();
         assert_eq!(avg_price, dec!(0.75));
         assert_eq!(filled_qty, dec!(4.0));
         assert_eq!(slippage, dec!(16.666666667));
@@ -456,11 +646,32 @@ mod tests_dry_compute {
         orderbook.initialize(
             vec![],
             vec![(dec!(0.9), dec!(1)), (dec!(0.8), dec!(1)), (dec!(0.7), dec!(1)), (dec!(0.6), dec!(1))],
+            None,
         );
 
-        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(1.5), true, false);
+        let (avg_price, filled_qty, slippage) = orderbook.compute_dry(dec!(1.5), true, false).unwrap();
         assert_eq!(avg_price, dec!(0.857142857));
         assert_eq!(filled_qty, dec!(1.75));
         assert_eq!(slippage, dec!(4.761904762));
     }
+
+    #[test]
+    fn test_compute_dry_errors() {
+        let mut orderbook = OrderBook::with_symbol("BTCUSDT");
+        orderbook.initialize(vec![(dec!(1.0), dec!(1))], vec![], None);
+
+        assert_eq!(orderbook.compute_dry(dec!(0.5), false, true).unwrap(), (dec!(1), dec!(0.5), dec!(0)));
+        assert_eq!(orderbook.compute_dry(dec!(2), false, true).unwrap_err().code(), "INSUFFICIENT_LIQUIDITY");
+        assert_eq!(orderbook.compute_dry(dec!(1), false, false).unwrap_err().code(), "EMPTY_BOOK");
+        assert_eq!(orderbook.compute_dry(dec!(0), false, true).unwrap_err().code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_compute_dry_or_zeros() {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(vec![(dec!(1.0), dec!(1))], vec![], None);
+        assert_eq!(orderbook.compute_dry_or_zeros(dec!(0.5), false, true), (dec!(1), dec!(0.5), dec!(0)));
+        assert_eq!(orderbook.compute_dry_or_zeros(dec!(2), false, true), (dec!(0), dec!(0), dec!(0)));
+    }
 }
@@ -0,0 +1,241 @@
+use rust_decimal::Decimal;
+use std::ops::Bound;
+use std::sync::{Mutex, MutexGuard};
+
+use super::fill::slippage;
+use super::market;
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+// Running totals of one side up to and including `price`, in taker order
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PrefixLevel {
+    price: Decimal,
+    cumulative_base: Decimal,
+    cumulative_quote: Decimal,
+}
+
+/// Cumulative base and quote per level, summed by `compute_dry` only as deep as the quoted
+/// amount reaches and cut back by every level update from the updated price onward.
+/// A quote after a top of book update re-sums what a linear walk would, the next quotes within
+/// the summed depth are a binary search.
+/// Behind a `Mutex` rather than a `RefCell` so `OrderBook` stays `Sync`.
+/// Not part of the book state: ignored by `==` and never serialized
+#[derive(Debug, Default)]
+pub(crate) struct PrefixCache {
+    asks: Mutex<Vec<PrefixLevel>>,
+    bids: Mutex<Vec<PrefixLevel>>,
+}
+
+impl Clone for PrefixCache {
+    fn clone(&self) -> Self {
+        PrefixCache {
+            asks: Mutex::new(self.side(true).clone()),
+            bids: Mutex::new(self.side(false).clone()),
+        }
+    }
+}
+
+impl PartialEq for PrefixCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl PrefixCache {
+    // The totals are rebuilt from the book on demand, so a poisoned lock is still usable
+    fn side(&self, is_ask: bool) -> MutexGuard<'_, Vec<PrefixLevel>> {
+        let side = if is_ask { &self.asks } else { &self.bids };
+        side.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn side_mut(&mut self, is_ask: bool) -> &mut Vec<PrefixLevel> {
+        let side = if is_ask { &mut self.asks } else { &mut self.bids };
+        side.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Drop the totals of `price` and every level behind it
+    pub(crate) fn invalidate(&mut self, is_ask: bool, price: Decimal) {
+        let levels = self.side_mut(is_ask);
+        let keep = levels.partition_point(|level| if is_ask { level.price < price } else { level.price > price });
+        levels.truncate(keep);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.side_mut(true).clear();
+        self.side_mut(false).clear();
+    }
+}
+
+impl OrderBook {
+    /// `compute_dry` for several amounts at once, e.g. a slippage table for 1k, 10k and 100k notional
+    /// with `fill_by_quote`. Each result is the same as a separate `compute_dry` call
+    pub fn compute_dry_batch(&self, fill_amounts: &[Decimal], fill_by_quote: bool, is_buy: bool) -> Vec<CoreResult<(Decimal, Decimal, Decimal)>> {
+        fill_amounts
+            .iter()
+            .map(|fill_amount| self.prefix_dry(*fill_amount, fill_by_quote, is_buy))
+            .collect()
+    }
+
+    // Same numbers as walking the book with `walk_levels`, found by binary search on the totals
+    pub(crate) fn prefix_dry(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> CoreResult<(Decimal, Decimal, Decimal)> {
+        if fill_amount <= Decimal::ZERO {
+            return Err(CoreError::InvalidInput(format!("Fill amount must be positive, got {}", fill_amount)));
+        }
+        let dry = self.prefix_fill(fill_amount, fill_by_quote, is_buy);
+        if dry.1.is_zero() {
+            return Err(self.unfillable(fill_amount, is_buy));
+        }
+        let best_price = if is_buy { self.asks.keys().next() } else { self.bids.keys().next_back() };
+        let best_price = best_price.copied().unwrap_or_default();
+        let rounded = market::round_dry(self.market.as_ref(), dry, best_price, is_buy, |base| self.prefix_fill(base, false, is_buy));
+        if rounded.1.is_zero() {
+            return Err(CoreError::InvalidInput(format!("Fill of {} base is below one lot", dry.1)));
+        }
+        Ok(rounded)
+    }
+
+    // `prefix_dry` before the market spec rounding
+    fn prefix_fill(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool) -> (Decimal, Decimal, Decimal) {
+        const INCOMPLETE: (Decimal, Decimal, Decimal) = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO);
+        if fill_amount <= Decimal::ZERO {
+            return INCOMPLETE;
+        }
+        let filled = |level: &PrefixLevel| if fill_by_quote { level.cumulative_quote } else { level.cumulative_base };
+        let mut levels = self.prefix.side(is_buy);
+        let covered = match levels.last() {
+            Some(last) => filled(last) >= fill_amount,
+            None => false,
+        };
+        if !covered {
+            self.extend_prefix(&mut levels, is_buy, |level| filled(level) >= fill_amount);
+        }
+
+        let index = levels.partition_point(|level| filled(level) < fill_amount);
+        let (best_price, level) = match (levels.first(), levels.get(index)) {
+            (Some(best), Some(level)) => (best.price, level),
+            _ => return INCOMPLETE,
+        };
+        let (total_base, total_quote) = if filled(level) == fill_amount {
+            (level.cumulative_base, level.cumulative_quote)
+        } else {
+            let (before_base, before_quote, before_filled) = match index.checked_sub(1).map(|before| &levels[before]) {
+                Some(before) => (before.cumulative_base, before.cumulative_quote, filled(before)),
+                None => (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO),
+            };
+            let remaining_amount = fill_amount - before_filled;
+            let remaining_quantity = if fill_by_quote { remaining_amount / level.price } else { remaining_amount };
+            (before_base + remaining_quantity, before_quote + remaining_quantity * level.price)
+        };
+        if total_base.is_zero() {
+            return INCOMPLETE;
+        }
+
+        let avg_price = total_quote / total_base;
+        let slippage = slippage(avg_price, best_price, is_buy);
+        (avg_price.round_dp(9), total_base.round_dp(9), slippage.round_dp(9))
+    }
+
+    // Sum the levels past the last cached one until `covered` holds for the newest total
+    fn extend_prefix(&self, levels: &mut Vec<PrefixLevel>, is_buy: bool, covered: impl Fn(&PrefixLevel) -> bool) {
+        let (from, mut base, mut quote) = match levels.last() {
+            Some(last) => (Bound::Excluded(last.price), last.cumulative_base, last.cumulative_quote),
+            None => (Bound::Unbounded, Decimal::ZERO, Decimal::ZERO),
+        };
+        let uncached: Box<dyn Iterator<Item = (&Decimal, &Decimal)>> = if is_buy {
+            Box::new(self.asks.range((from, Bound::Unbounded)))
+        } else {
+            Box::new(self.bids.range((Bound::Unbounded, from)).rev())
+        };
+        for (price, quantity) in uncached {
+            base += quantity;
+            quote += quantity * price;
+            let level = PrefixLevel { price: *price, cumulative_base: base, cumulative_quote: quote };
+            levels.push(level);
+            if covered(&level) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBookDelta;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(
+            vec![(dec!(100), dec!(1)), (dec!(101), dec!(2)), (dec!(102.5), dec!(3)), (dec!(105), dec!(10))],
+            vec![(dec!(99), dec!(1.5)), (dec!(98), dec!(2)), (dec!(95), dec!(4))],
+            None,
+        );
+        order_book
+    }
+
+    fn assert_matches_walk(order_book: &OrderBook) {
+        let amounts = [dec!(0), dec!(0.5), dec!(1), dec!(2.75), dec!(6), dec!(7.5), dec!(16), dec!(17), dec!(99.5), dec!(250), dec!(1533.7), dec!(5000)];
+        for fill_by_quote in [false, true] {
+            for is_buy in [false, true] {
+                let batch = order_book.compute_dry_batch(&amounts, fill_by_quote, is_buy);
+                for (amount, prefix_result) in amounts.iter().zip(batch) {
+                    let expected = match order_book.compute_fill(*amount, fill_by_quote, is_buy) {
+                        Ok(walked) if walked.is_complete => Some((walked.avg_price, walked.filled_base, walked.slippage)),
+                        _ => None,
+                    };
+                    assert_eq!(prefix_result.ok(), expected, "amount {} by quote {} buy {}", amount, fill_by_quote, is_buy);
+                    assert_eq!(order_book.compute_dry(*amount, fill_by_quote, is_buy).ok(), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefix_matches_walk() {
+        let order_book = setup_order_book();
+        assert_matches_walk(&order_book);
+        assert_eq!(order_book.compute_dry(dec!(3), false, true).unwrap(), (dec!(100.666666667), dec!(3), dec!(0.666666667)));
+    }
+
+    #[test]
+    fn test_prefix_follows_updates() {
+        let mut order_book = setup_order_book();
+        assert_matches_walk(&order_book);
+
+        order_book.update_order(true, vec![(dec!(101), dec!(0)), (dec!(103), dec!(1))]).unwrap();
+        assert_eq!(order_book.prefix.side(true).len(), 1);
+        assert_matches_walk(&order_book);
+
+        order_book.update_order(false, vec![(dec!(99.5), dec!(1))]).unwrap();
+        assert!(order_book.prefix.side(false).is_empty());
+        assert_matches_walk(&order_book);
+
+        order_book.apply_delta(OrderBookDelta::new(1, 1, vec![(dec!(100), dec!(0.25))], vec![(dec!(95), dec!(0))]));
+        assert_matches_walk(&order_book);
+
+        order_book.initialize(vec![(dec!(200), dec!(1))], vec![], None);
+        assert_eq!(order_book.compute_dry(dec!(1), false, true).unwrap(), (dec!(200), dec!(1), dec!(0)));
+        assert_matches_walk(&order_book);
+    }
+
+    #[test]
+    fn test_prefix_sums_only_what_the_quote_needs() {
+        let order_book = setup_order_book();
+        // 2 of base takes 100 and 101, the 102.5 and 105 levels are left alone
+        assert_eq!(order_book.compute_dry(dec!(2), false, true).unwrap(), (dec!(100.5), dec!(2), dec!(0.5)));
+        assert_eq!(order_book.prefix.side(true).len(), 2);
+        assert_eq!(order_book.compute_dry(dec!(1), false, true).unwrap(), (dec!(100), dec!(1), dec!(0)));
+        assert_eq!(order_book.prefix.side(true).len(), 2);
+        assert_eq!(order_book.compute_dry(dec!(7), false, true).unwrap(), (dec!(102.071428571), dec!(7), dec!(2.071428571)));
+        assert_eq!(order_book.prefix.side(true).len(), 4);
+        assert_matches_walk(&order_book);
+    }
+
+    #[test]
+    fn test_order_book_is_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<OrderBook>();
+    }
+}
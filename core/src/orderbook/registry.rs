@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use super::OrderBook;
+use crate::error::{CoreError, CoreResult};
+
+/// Order books of several markets keyed by symbol, so switching pairs keeps the other books around
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrderBookRegistry {
+    books: HashMap<String, OrderBook>,
+}
+
+impl OrderBookRegistry {
+    pub fn new() -> Self {
+        Self { books: HashMap::new() }
+    }
+
+    /// Add an empty book for `symbol`, fails if the symbol is already registered
+    pub fn create(&mut self, symbol: &str) -> CoreResult<&mut OrderBook> {
+        if self.books.contains_key(symbol) {
+            return Err(CoreError::InvalidInput(format!("Order book {} already exists", symbol)));
+        }
+        Ok(self.books.entry(symbol.to_string()).or_insert_with(|| OrderBook::with_symbol(symbol)))
+    }
+
+    pub fn get_or_create(&mut self, symbol: &str) -> &mut OrderBook {
+        self.books.entry(symbol.to_string()).or_insert_with(|| OrderBook::with_symbol(symbol))
+    }
+
+    /// Register a book under its own symbol, returning the book it replaced if any
+    pub fn insert(&mut self, order_book: OrderBook) -> Option<OrderBook> {
+        self.books.insert(order_book.symbol.clone(), order_book)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    pub fn get_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        self.books.get_mut(symbol)
+    }
+
+    pub fn remove(&mut self, symbol: &str) -> Option<OrderBook> {
+        self.books.remove(symbol)
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.books.contains_key(symbol)
+    }
+
+    /// Registered symbols, sorted
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.books.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_registry_create_get_remove() {
+        let mut registry = OrderBookRegistry::new();
+        registry
+            .create("BTCBUSD")
+            .unwrap()
+            .initialize(vec![(dec!(100), dec!(1))], vec![(dec!(99), dec!(1))], None);
+        registry.create("ETHBUSD").unwrap();
+        assert!(registry.create("BTCBUSD").is_err());
+
+        assert_eq!(registry.symbols(), vec!["BTCBUSD".to_string(), "ETHBUSD".to_string()]);
+        assert_eq!(registry.get("BTCBUSD").unwrap().get_best_ask_bid(), (Some(dec!(100)), Some(dec!(99))));
+        assert_eq!(registry.get("ETHBUSD").unwrap().symbol, "ETHBUSD");
+        assert!(registry.get("SOLBUSD").is_none());
+
+        // books are independent
+        registry.get_mut("ETHBUSD").unwrap().update_order(true, vec![(dec!(2000), dec!(3))]).unwrap();
+        assert_eq!(registry.get("BTCBUSD").unwrap().asks.len(), 1);
+
+        let removed = registry.remove("BTCBUSD").unwrap();
+        assert_eq!(removed.symbol, "BTCBUSD");
+        assert!(!registry.contains("BTCBUSD"));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_registry_get_or_create_and_insert() {
+        let mut registry = OrderBookRegistry::new();
+        registry.get_or_create("BTCBUSD").update_order(false, vec![(dec!(99), dec!(1))]).unwrap();
+        assert_eq!(registry.get_or_create("BTCBUSD").bids.len(), 1);
+
+        let replaced = registry.insert(OrderBook::with_symbol("BTCBUSD"));
+        assert_eq!(replaced.unwrap().bids.len(), 1);
+        assert!(registry.get("BTCBUSD").unwrap().bids.is_empty());
+    }
+}
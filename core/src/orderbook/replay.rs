@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+
+use super::{OrderBook, OrderBookDelta, PriceLevel};
+use crate::error::{CoreError, CoreResult};
+
+/// A book message as it was received, before it touched the book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplayMessage {
+    /// Replayed with `initialize`
+    Snapshot { asks: Vec<PriceLevel>, bids: Vec<PriceLevel>, sequence: Option<u64> },
+    /// Unsequenced levels, replayed with `update_order` on asks then bids
+    Update { asks: Vec<PriceLevel>, bids: Vec<PriceLevel> },
+    /// Sequenced levels, replayed with `apply_delta`
+    Delta { first_sequence: u64, last_sequence: u64, asks: Vec<PriceLevel>, bids: Vec<PriceLevel> },
+}
+
+/// One line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayRecord {
+    /// Milliseconds since epoch when the message was received
+    pub timestamp: u64,
+    pub symbol: String,
+    pub message: ReplayMessage,
+}
+
+impl ReplayMessage {
+    pub fn apply_to(&self, orderbook: &mut OrderBook) -> CoreResult<()> {
+        match self.clone() {
+            ReplayMessage::Snapshot { asks, bids, sequence } => orderbook.initialize(asks, bids, sequence),
+            ReplayMessage::Update { asks, bids } => {
+                orderbook.update_order(true, asks)?;
+                orderbook.update_order(false, bids)?;
+            }
+            ReplayMessage::Delta { first_sequence, last_sequence, asks, bids } => {
+                orderbook.apply_delta(OrderBookDelta::new(first_sequence, last_sequence, asks, bids));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes book messages as newline delimited JSON, one `ReplayRecord` per line
+pub struct OrderBookRecorder<W: Write> {
+    writer: W,
+}
+
+impl OrderBookRecorder<BufWriter<File>> {
+    /// Record to `path`, truncating an existing file
+    pub fn create(path: impl AsRef<Path>) -> CoreResult<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> OrderBookRecorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Append one message. Every line is flushed so a crash loses at most the message being written
+    pub fn record(&mut self, timestamp: u64, symbol: &str, message: ReplayMessage) -> CoreResult<()> {
+        let record = ReplayRecord { timestamp, symbol: symbol.to_string(), message };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Drives the `OrderBook` of one symbol through a recording so its state can be inspected at any point.
+/// A recording may interleave several symbols (the wasm manager records every pair it routes),
+/// replay each one with its own replayer
+#[derive(Debug, Clone)]
+pub struct OrderBookReplayer {
+    records: Vec<ReplayRecord>,
+    // index of the next record to apply
+    position: usize,
+    orderbook: OrderBook,
+}
+
+impl OrderBookReplayer {
+    /// Replay the records of `symbol` in order, the other symbols are skipped
+    pub fn new(records: Vec<ReplayRecord>, symbol: &str) -> Self {
+        let records: Vec<ReplayRecord> = records.into_iter().filter(|record| record.symbol == symbol).collect();
+        Self { records, position: 0, orderbook: OrderBook::with_symbol(symbol) }
+    }
+
+    /// Read a recording made by `OrderBookRecorder`, blank lines are skipped
+    pub fn from_reader(reader: impl BufRead, symbol: &str) -> CoreResult<Self> {
+        let mut records = vec![];
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line)
+                .map_err(|e| CoreError::InvalidInput(format!("Invalid record on line {}: {}", index + 1, e)))?;
+            records.push(record);
+        }
+        Ok(Self::new(records, symbol))
+    }
+
+    pub fn open(path: impl AsRef<Path>, symbol: &str) -> CoreResult<Self> {
+        Self::from_reader(std::io::BufReader::new(File::open(path)?), symbol)
+    }
+
+    /// The book after every record applied so far
+    pub fn orderbook(&self) -> &OrderBook {
+        &self.orderbook
+    }
+
+    pub fn records(&self) -> &[ReplayRecord] {
+        &self.records
+    }
+
+    /// Number of records applied so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Timestamp of the last record applied
+    pub fn timestamp(&self) -> Option<u64> {
+        self.position.checked_sub(1).map(|index| self.records[index].timestamp)
+    }
+
+    /// Start over from an empty book
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.orderbook = OrderBook::with_symbol(&self.orderbook.symbol);
+    }
+
+    /// Apply the next record, `None` at the end of the recording.
+    /// A record the book rejects is an error and the replay stays in front of it
+    pub fn step(&mut self) -> CoreResult<Option<&ReplayRecord>> {
+        let record = match self.records.get(self.position) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        record.message.apply_to(&mut self.orderbook)?;
+        self.position += 1;
+        Ok(Some(record))
+    }
+
+    /// State after every record received at or before `timestamp`
+    pub fn seek_timestamp(&mut self, timestamp: u64) -> CoreResult<&OrderBook> {
+        if self.timestamp().is_some_and(|current| current > timestamp) {
+            self.reset();
+        }
+        while self.records.get(self.position).is_some_and(|record| record.timestamp <= timestamp) {
+            self.step()?;
+        }
+        Ok(&self.orderbook)
+    }
+
+    /// State once the book reached `sequence`, or at the end of the recording when it never does
+    pub fn seek_sequence(&mut self, sequence: u64) -> CoreResult<&OrderBook> {
+        if self.orderbook.sequence.is_some_and(|current| current > sequence) {
+            self.reset();
+        }
+        loop {
+            let reached = self.orderbook.sequence.is_some_and(|current| current >= sequence);
+            if reached || self.step()?.is_none() {
+                break;
+            }
+        }
+        Ok(&self.orderbook)
+    }
+
+    /// Apply every remaining record
+    pub fn run_to_end(&mut self) -> CoreResult<&OrderBook> {
+        while self.step()?.is_some() {}
+        Ok(&self.orderbook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn record_session() -> Vec<u8> {
+        let mut recorder = OrderBookRecorder::new(vec![]);
+        let messages = [
+            (1_000, "BTC", ReplayMessage::Snapshot {
+                asks: vec![(dec!(100.10), dec!(1)), (dec!(101), dec!(2))],
+                bids: vec![(dec!(99), dec!(1))],
+                sequence: Some(10),
+            }),
+            (1_500, "ETH", ReplayMessage::Update { asks: vec![(dec!(10), dec!(1))], bids: vec![] }),
+            (2_000, "BTC", ReplayMessage::Delta {
+                first_sequence: 11,
+                last_sequence: 12,
+                asks: vec![(dec!(100.10), dec!(0))],
+                bids: vec![(dec!(99.5), dec!(3))],
+            }),
+            (3_000, "BTC", ReplayMessage::Update { asks: vec![(dec!(100.5), dec!(4))], bids: vec![] }),
+        ];
+        for (timestamp, symbol, message) in messages {
+            recorder.record(timestamp, symbol, message).unwrap();
+        }
+        recorder.into_inner()
+    }
+
+    #[test]
+    fn test_record_is_ndjson() {
+        let recording = String::from_utf8(record_session()).unwrap();
+        assert_eq!(recording.lines().count(), 4);
+        let first: serde_json::Value = serde_json::from_str(recording.lines().next().unwrap()).unwrap();
+        assert_eq!(first["message"]["type"], "snapshot");
+        // decimals keep the exact text they were received with
+        assert_eq!(first["message"]["asks"][0][0], "100.10");
+    }
+
+    #[test]
+    fn test_replay_seek() {
+        let recording = record_session();
+        let mut replayer = OrderBookReplayer::from_reader(recording.as_slice(), "BTC").unwrap();
+        assert_eq!(replayer.records().len(), 3);
+        assert_eq!(replayer.orderbook().symbol, "BTC");
+
+        let orderbook = replayer.seek_timestamp(1_999).unwrap();
+        assert_eq!(orderbook.get_best_ask_bid(), (Some(dec!(100.1)), Some(dec!(99))));
+
+        let orderbook = replayer.seek_sequence(12).unwrap();
+        assert_eq!(orderbook.get_best_ask_bid(), (Some(dec!(101)), Some(dec!(99.5))));
+        assert_eq!(replayer.timestamp(), Some(2_000));
+
+        replayer.run_to_end().unwrap();
+        assert_eq!(replayer.orderbook().get_best_ask_bid().0, Some(dec!(100.5)));
+
+        // seeking back replays from the start
+        let orderbook = replayer.seek_timestamp(1_000).unwrap();
+        assert_eq!(orderbook.sequence, Some(10));
+        assert_eq!(replayer.position(), 1);
+    }
+
+    #[test]
+    fn test_replay_keeps_symbols_apart() {
+        let recording = record_session();
+        let mut eth = OrderBookReplayer::from_reader(recording.as_slice(), "ETH").unwrap();
+        assert_eq!(eth.records().len(), 1);
+        assert_eq!(eth.run_to_end().unwrap().get_depth(), (vec![(dec!(10), dec!(1))], vec![]));
+
+        // the ETH update does not leak into the BTC book
+        let mut btc = OrderBookReplayer::from_reader(recording.as_slice(), "BTC").unwrap();
+        assert!(!btc.run_to_end().unwrap().asks().contains_key(&dec!(10)));
+    }
+
+    #[test]
+    fn test_replay_stops_at_a_rejected_record() {
+        let update = ReplayMessage::Update { asks: vec![(dec!(101), dec!(-1))], bids: vec![] };
+        let mut replayer = OrderBookReplayer::new(vec![ReplayRecord { timestamp: 1, symbol: "BTC".to_string(), message: update }], "BTC");
+        assert_eq!(replayer.run_to_end().unwrap_err().code(), "INVALID_INPUT");
+        assert_eq!(replayer.position(), 0);
+    }
+
+    #[test]
+    fn test_replay_invalid_line() {
+        let error = OrderBookReplayer::from_reader("\n{\"timestamp\":1}\n".as_bytes(), "BTC").unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+    }
+}
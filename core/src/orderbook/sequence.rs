@@ -0,0 +1,140 @@
+use super::{OrderBook, PriceLevel};
+
+/// A batch of level updates covering the sequence range `first_sequence..=last_sequence`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBookDelta {
+    pub first_sequence: u64,
+    pub last_sequence: u64,
+    pub asks: Vec<PriceLevel>,
+    pub bids: Vec<PriceLevel>,
+}
+
+impl OrderBookDelta {
+    pub fn new(first_sequence: u64, last_sequence: u64, asks: Vec<PriceLevel>, bids: Vec<PriceLevel>) -> Self {
+        Self {
+            first_sequence,
+            last_sequence,
+            asks,
+            bids,
+        }
+    }
+}
+
+/// What `OrderBook::apply_delta` did with a delta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOutcome {
+    /// The delta continued the book and has been applied
+    Applied,
+    /// The delta is entirely older than the book and has been ignored
+    Stale,
+    /// One or more deltas are missing between the book and this delta.
+    /// Nothing has been applied, the caller should refetch a snapshot
+    Gap { expected: u64, received: u64 },
+}
+
+impl DeltaOutcome {
+    pub fn is_gap(&self) -> bool {
+        matches!(self, DeltaOutcome::Gap { .. })
+    }
+}
+
+impl OrderBook {
+    /// Apply a sequenced delta on top of the current book.
+    ///
+    /// A delta is accepted when it covers the next expected sequence (`sequence + 1`),
+    /// overlapping deltas are fine since levels carry absolute quantities.
+    /// An unsequenced book adopts the sequence of the first delta it receives.
+    pub fn apply_delta(&mut self, delta: OrderBookDelta) -> DeltaOutcome {
+        if let Some(current) = self.sequence {
+            if delta.last_sequence <= current {
+                return DeltaOutcome::Stale;
+            }
+            let expected = current + 1;
+            if delta.first_sequence > expected {
+                return DeltaOutcome::Gap {
+                    expected,
+                    received: delta.first_sequence,
+                };
+            }
+        }
+
+        // both sides first, so a delta is not judged on its half applied state
+        let best_before = self.get_best_ask_bid();
+        self.apply_levels(true, delta.asks);
+        self.apply_levels(false, delta.bids);
+        self.check_cross(None);
+        self.emit_best_change(best_before);
+        self.sequence = Some(delta.last_sequence);
+        DeltaOutcome::Applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(
+            vec![(dec!(100), dec!(1)), (dec!(101), dec!(2))],
+            vec![(dec!(99), dec!(1)), (dec!(98), dec!(2))],
+            Some(10),
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_apply_delta_in_order() {
+        let mut order_book = setup_order_book();
+
+        let outcome = order_book.apply_delta(OrderBookDelta::new(11, 12, vec![(dec!(100), dec!(3))], vec![(dec!(99), dec!(0))]));
+        assert_eq!(outcome, DeltaOutcome::Applied);
+        assert_eq!(order_book.sequence, Some(12));
+        assert_eq!(order_book.asks[&dec!(100)], dec!(3));
+        assert!(!order_book.bids.contains_key(&dec!(99)));
+
+        // overlapping delta still continues the book
+        let outcome = order_book.apply_delta(OrderBookDelta::new(12, 14, vec![(dec!(102), dec!(1))], vec![]));
+        assert_eq!(outcome, DeltaOutcome::Applied);
+        assert_eq!(order_book.sequence, Some(14));
+    }
+
+    #[test]
+    fn test_apply_delta_rejects_stale() {
+        let mut order_book = setup_order_book();
+
+        let outcome = order_book.apply_delta(OrderBookDelta::new(9, 10, vec![(dec!(100), dec!(5))], vec![]));
+        assert_eq!(outcome, DeltaOutcome::Stale);
+        assert_eq!(order_book.sequence, Some(10));
+        assert_eq!(order_book.asks[&dec!(100)], dec!(1));
+    }
+
+    #[test]
+    fn test_apply_delta_reports_gap() {
+        let mut order_book = setup_order_book();
+
+        let outcome = order_book.apply_delta(OrderBookDelta::new(13, 14, vec![(dec!(100), dec!(5))], vec![]));
+        assert_eq!(outcome, DeltaOutcome::Gap { expected: 11, received: 13 });
+        assert_eq!(order_book.sequence, Some(10));
+        assert_eq!(order_book.asks[&dec!(100)], dec!(1));
+
+        // the book keeps reporting the gap until a new snapshot is loaded
+        let outcome = order_book.apply_delta(OrderBookDelta::new(15, 15, vec![], vec![]));
+        assert!(outcome.is_gap());
+
+        order_book.initialize(vec![(dec!(100), dec!(5))], vec![], Some(15));
+        let outcome = order_book.apply_delta(OrderBookDelta::new(16, 16, vec![], vec![(dec!(99), dec!(1))]));
+        assert_eq!(outcome, DeltaOutcome::Applied);
+    }
+
+    #[test]
+    fn test_apply_delta_unsequenced_book_adopts_sequence() {
+        let mut order_book = OrderBook::new();
+        order_book.initialize(vec![(dec!(100), dec!(1))], vec![], None);
+
+        let outcome = order_book.apply_delta(OrderBookDelta::new(40, 42, vec![], vec![(dec!(99), dec!(1))]));
+        assert_eq!(outcome, DeltaOutcome::Applied);
+        assert_eq!(order_book.sequence, Some(42));
+    }
+}
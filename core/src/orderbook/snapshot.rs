@@ -0,0 +1,216 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::{OrderBook, PriceLevel};
+use crate::error::{CoreError, CoreResult};
+
+/// Version written by `to_json` / `to_bytes`, bump it whenever the layout changes
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Portable copy of an `OrderBook`, e.g. to move it between a web worker and the main thread
+/// or to attach it to a bug report.
+/// Levels are stored best first on both sides, like `get_depth`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBookSnapshot {
+    pub version: u8,
+    pub symbol: String,
+    pub sequence: Option<u64>,
+    /// Milliseconds since epoch when the snapshot was taken
+    pub timestamp: u64,
+    pub asks: Vec<PriceLevel>,
+    pub bids: Vec<PriceLevel>,
+}
+
+impl OrderBook {
+    pub fn to_snapshot(&self, timestamp: u64) -> OrderBookSnapshot {
+        let (asks, bids) = self.get_depth();
+        OrderBookSnapshot {
+            version: SNAPSHOT_VERSION,
+            symbol: self.symbol.clone(),
+            sequence: self.sequence,
+            timestamp,
+            asks,
+            bids,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: OrderBookSnapshot) -> Self {
+        let mut order_book = OrderBook::with_symbol(&snapshot.symbol);
+        order_book.initialize(snapshot.asks, snapshot.bids, snapshot.sequence);
+        order_book
+    }
+}
+
+impl OrderBookSnapshot {
+    pub fn to_json(&self) -> CoreResult<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> CoreResult<Self> {
+        let snapshot: OrderBookSnapshot = serde_json::from_str(json)?;
+        check_version(snapshot.version)?;
+        Ok(snapshot)
+    }
+
+    /// Compact little endian encoding:
+    /// `version u8 | symbol len u16 + utf8 | has_sequence u8 + sequence u64 | timestamp u64 |
+    ///  asks count u32 + (price, quantity) | bids count u32 + (price, quantity)`
+    /// where every decimal takes its 16 byte `Decimal::serialize` form.
+    /// A symbol or side too long for its length prefix is an `InvalidInput` error
+    pub fn to_bytes(&self) -> CoreResult<Vec<u8>> {
+        let levels = self.asks.len() + self.bids.len();
+        let mut bytes = Vec::with_capacity(32 + self.symbol.len() + levels * 32);
+        bytes.push(self.version);
+        let symbol_len = u16::try_from(self.symbol.len())
+            .map_err(|_| CoreError::InvalidInput(format!("Symbol of {} bytes is too long to encode", self.symbol.len())))?;
+        bytes.extend_from_slice(&symbol_len.to_le_bytes());
+        bytes.extend_from_slice(self.symbol.as_bytes());
+        bytes.push(self.sequence.is_some() as u8);
+        bytes.extend_from_slice(&self.sequence.unwrap_or_default().to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        for levels in [&self.asks, &self.bids] {
+            let count = u32::try_from(levels.len())
+                .map_err(|_| CoreError::InvalidInput(format!("{} levels are too many to encode", levels.len())))?;
+            bytes.extend_from_slice(&count.to_le_bytes());
+            for (price, quantity) in levels {
+                bytes.extend_from_slice(&price.serialize());
+                bytes.extend_from_slice(&quantity.serialize());
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> CoreResult<Self> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        let version = reader.take::<1>()?[0];
+        check_version(version)?;
+        let symbol_len = u16::from_le_bytes(reader.take()?) as usize;
+        let symbol = String::from_utf8(reader.take_slice(symbol_len)?.to_vec())
+            .map_err(|e| CoreError::InvalidInput(format!("Invalid snapshot symbol: {}", e)))?;
+        let has_sequence = reader.take::<1>()?[0] != 0;
+        let sequence = u64::from_le_bytes(reader.take()?);
+        let timestamp = u64::from_le_bytes(reader.take()?);
+        let asks = reader.take_levels()?;
+        let bids = reader.take_levels()?;
+        if reader.position != bytes.len() {
+            return Err(CoreError::InvalidInput(format!("Invalid snapshot: {} trailing bytes", bytes.len() - reader.position)));
+        }
+        Ok(OrderBookSnapshot {
+            version,
+            symbol,
+            sequence: if has_sequence { Some(sequence) } else { None },
+            timestamp,
+            asks,
+            bids,
+        })
+    }
+}
+
+fn check_version(version: u8) -> CoreResult<()> {
+    if version != SNAPSHOT_VERSION {
+        return Err(CoreError::InvalidInput(format!("Unsupported snapshot version {}, expected {}", version, SNAPSHOT_VERSION)));
+    }
+    Ok(())
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take_slice(&mut self, len: usize) -> CoreResult<&'a [u8]> {
+        let end = self.position + len;
+        if end > self.bytes.len() {
+            return Err(CoreError::InvalidInput("Invalid snapshot: unexpected end of data".to_string()));
+        }
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn take<const N: usize>(&mut self) -> CoreResult<[u8; N]> {
+        let mut buf = [0u8; N];
+        buf.copy_from_slice(self.take_slice(N)?);
+        Ok(buf)
+    }
+
+    fn take_levels(&mut self) -> CoreResult<Vec<PriceLevel>> {
+        let count = u32::from_le_bytes(self.take()?) as usize;
+        let mut levels = Vec::with_capacity(count.min(self.bytes.len() / 32));
+        for _ in 0..count {
+            let price = Decimal::deserialize(self.take()?);
+            let quantity = Decimal::deserialize(self.take()?);
+            levels.push((price, quantity));
+        }
+        Ok(levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_order_book() -> OrderBook {
+        let mut order_book = OrderBook::with_symbol("BTCBUSD");
+        order_book.initialize(
+            vec![(dec!(100.10), dec!(1)), (dec!(101), dec!(2.5))],
+            vec![(dec!(99.9), dec!(1.25)), (dec!(98), dec!(3))],
+            Some(42),
+        );
+        order_book
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let order_book = setup_order_book();
+        let snapshot = order_book.to_snapshot(1_700_000_000_000);
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.symbol, "BTCBUSD");
+        assert_eq!(snapshot.sequence, Some(42));
+        assert_eq!(snapshot.bids[0], (dec!(99.9), dec!(1.25)));
+
+        assert_eq!(OrderBook::from_snapshot(snapshot), order_book);
+    }
+
+    #[test]
+    fn test_snapshot_json_round_trip() {
+        let snapshot = setup_order_book().to_snapshot(1_700_000_000_000);
+        let json = snapshot.to_json().unwrap();
+        let restored = OrderBookSnapshot::from_json(&json).unwrap();
+        assert_eq!(restored, snapshot);
+        // decimals keep their scale
+        assert_eq!(restored.asks[0].0.to_string(), "100.10");
+    }
+
+    #[test]
+    fn test_snapshot_bytes_round_trip() {
+        let snapshot = setup_order_book().to_snapshot(1_700_000_000_000);
+        let bytes = snapshot.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 1 + 2 + 7 + 1 + 8 + 8 + 4 + 2 * 32 + 4 + 2 * 32);
+        assert_eq!(OrderBookSnapshot::from_bytes(&bytes).unwrap(), snapshot);
+
+        let mut unsequenced = OrderBook::new().to_snapshot(0);
+        unsequenced.sequence = None;
+        assert_eq!(OrderBookSnapshot::from_bytes(&unsequenced.to_bytes().unwrap()).unwrap(), unsequenced);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_input() {
+        let snapshot = setup_order_book().to_snapshot(0);
+        let bytes = snapshot.to_bytes().unwrap();
+        assert!(OrderBookSnapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = SNAPSHOT_VERSION + 1;
+        assert!(OrderBookSnapshot::from_bytes(&wrong_version).is_err());
+
+        let json = snapshot.to_json().unwrap().replacen("\"version\":1", "\"version\":9", 1);
+        assert!(OrderBookSnapshot::from_json(&json).is_err());
+
+        let mut long_symbol = snapshot;
+        long_symbol.symbol = "X".repeat(u16::MAX as usize + 1);
+        assert_eq!(long_symbol.to_bytes().unwrap_err().code(), "INVALID_INPUT");
+    }
+}
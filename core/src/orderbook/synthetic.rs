@@ -0,0 +1,159 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::BTreeMap;
+
+use super::{OrderBook, PriceLevel};
+use crate::error::CoreResult;
+
+/// Which underlying book of a `SyntheticBook`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntheticLeg {
+    /// Book of the synthetic base against the common currency, ETH/USDT for ETH/BTC
+    Base,
+    /// Book of the synthetic quote against the common currency, BTC/USDT for ETH/BTC
+    Quote,
+}
+
+/// Book of a pair without a direct market, derived from two books sharing their quote currency,
+/// e.g. ETH/BTC from ETH/USDT and BTC/USDT.
+///
+/// Synthetic asks are what buying the base costs through both legs (lift base asks, hit quote
+/// bids), synthetic bids what selling it returns (hit base bids, lift quote asks). Levels are
+/// matched by common currency notional so walking the synthetic book walks both legs the way
+/// the real orders would. Prices are rounded against the taker (asks up, bids down) and
+/// quantities down, at 9 decimals. Leg fees are not included.
+/// The synthetic book is rebuilt whenever a leg changes through this type
+#[derive(Debug, Clone)]
+pub struct SyntheticBook {
+    base_leg: OrderBook,
+    quote_leg: OrderBook,
+    book: OrderBook,
+}
+
+impl SyntheticBook {
+    pub fn new(symbol: &str, base_leg: OrderBook, quote_leg: OrderBook) -> Self {
+        let mut synthetic = SyntheticBook { base_leg, quote_leg, book: OrderBook::with_symbol(symbol) };
+        synthetic.rebuild();
+        synthetic
+    }
+
+    /// The synthetic book, quote it like any `OrderBook` (`compute_dry`, `get_depth`, ...)
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    pub fn leg(&self, leg: SyntheticLeg) -> &OrderBook {
+        match leg {
+            SyntheticLeg::Base => &self.base_leg,
+            SyntheticLeg::Quote => &self.quote_leg,
+        }
+    }
+
+    pub fn initialize_leg(&mut self, leg: SyntheticLeg, asks: Vec<PriceLevel>, bids: Vec<PriceLevel>, sequence: Option<u64>) {
+        self.modify_leg(leg, |orderbook| orderbook.initialize(asks, bids, sequence));
+    }
+
+    pub fn update_leg(&mut self, leg: SyntheticLeg, is_ask: bool, updates: Vec<PriceLevel>) -> CoreResult<()> {
+        self.modify_leg(leg, |orderbook| orderbook.update_order(is_ask, updates))
+    }
+
+    /// Change a leg in any other way (`apply_delta`, ...), the synthetic book is rebuilt afterwards
+    pub fn modify_leg<R>(&mut self, leg: SyntheticLeg, modify: impl FnOnce(&mut OrderBook) -> R) -> R {
+        let result = match leg {
+            SyntheticLeg::Base => modify(&mut self.base_leg),
+            SyntheticLeg::Quote => modify(&mut self.quote_leg),
+        };
+        self.rebuild();
+        result
+    }
+
+    fn rebuild(&mut self) {
+        let asks = cross_levels(&self.base_leg, &self.quote_leg, true);
+        let bids = cross_levels(&self.base_leg, &self.quote_leg, false);
+        self.book.initialize(asks, bids, None);
+    }
+}
+
+// Synthetic levels of one side: base levels of `base_side` matched with quote levels of the
+// opposite side, chunk by chunk of common currency notional
+fn cross_levels(base_leg: &OrderBook, quote_leg: &OrderBook, is_ask: bool) -> Vec<PriceLevel> {
+    let price_strategy = if is_ask { RoundingStrategy::AwayFromZero } else { RoundingStrategy::ToZero };
+    let mut base_levels = base_leg.taker_levels(is_ask).map(|(price, quantity)| (price, price * quantity));
+    let mut quote_levels = quote_leg.taker_levels(!is_ask).map(|(price, quantity)| (price, price * quantity));
+
+    let mut levels: BTreeMap<Decimal, Decimal> = BTreeMap::new();
+    let (mut base_level, mut quote_level) = (base_levels.next(), quote_levels.next());
+    while let (Some((base_price, base_notional)), Some((quote_price, quote_notional))) = (base_level, quote_level) {
+        let notional = base_notional.min(quote_notional);
+        if base_price.is_zero() || quote_price.is_zero() {
+            break;
+        }
+        let price = (base_price / quote_price).round_dp_with_strategy(9, price_strategy);
+        let quantity = (notional / base_price).round_dp_with_strategy(9, RoundingStrategy::ToZero);
+        if !quantity.is_zero() {
+            *levels.entry(price).or_insert(Decimal::ZERO) += quantity;
+        }
+
+        base_level = if base_notional > notional { Some((base_price, base_notional - notional)) } else { base_levels.next() };
+        quote_level = if quote_notional > notional { Some((quote_price, quote_notional - notional)) } else { quote_levels.next() };
+    }
+    levels.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn setup_synthetic_book() -> SyntheticBook {
+        let mut eth_usdt = OrderBook::with_symbol("ETHUSDT");
+        eth_usdt.initialize(
+            vec![(dec!(2000), dec!(1)), (dec!(2010), dec!(2))],
+            vec![(dec!(1990), dec!(1)), (dec!(1980), dec!(3))],
+            None,
+        );
+        let mut btc_usdt = OrderBook::with_symbol("BTCUSDT");
+        btc_usdt.initialize(
+            vec![(dec!(40100), dec!(0.1)), (dec!(40200), dec!(1))],
+            vec![(dec!(40000), dec!(0.05)), (dec!(39900), dec!(1))],
+            None,
+        );
+        SyntheticBook::new("ETHBTC", eth_usdt, btc_usdt)
+    }
+
+    #[test]
+    fn test_synthetic_levels() {
+        let synthetic = setup_synthetic_book();
+        let (asks, bids) = synthetic.book().get_depth();
+        // 2000 USDT of ETH at 2000 against 2000 USDT of BTC at 40000
+        assert_eq!(asks[0], (dec!(0.05), dec!(1)));
+        // 2010 / 39900 rounded up
+        assert_eq!(asks[1], (dec!(0.050375940), dec!(2)));
+        // 1990 / 40100 rounded down
+        assert_eq!(bids[0], (dec!(0.049625935), dec!(1)));
+        assert_eq!(synthetic.book().symbol, "ETHBTC");
+    }
+
+    #[test]
+    fn test_compute_dry_matches_two_legs() {
+        let synthetic = setup_synthetic_book();
+        let estimate = synthetic.book().compute_fill(dec!(1.5), false, true).unwrap();
+        assert!(estimate.is_complete);
+
+        // buy 1.5 ETH for USDT, then sell the BTC needed to raise that USDT
+        let usdt = synthetic.leg(SyntheticLeg::Base).compute_fill(dec!(1.5), false, true).unwrap().filled_quote;
+        let btc = synthetic.leg(SyntheticLeg::Quote).compute_fill(usdt, true, false).unwrap().filled_base;
+        // never cheaper than the real execution, off by the price rounding only
+        assert!(estimate.filled_quote >= btc);
+        assert!(estimate.filled_quote - btc < dec!(0.000000001));
+    }
+
+    #[test]
+    fn test_rebuilt_on_leg_change() {
+        let mut synthetic = setup_synthetic_book();
+        synthetic.update_leg(SyntheticLeg::Quote, false, vec![(dec!(40000), dec!(0))]).unwrap();
+        assert_eq!(synthetic.book().get_best_ask_bid().0, Some(dec!(0.050125314)));
+
+        synthetic.initialize_leg(SyntheticLeg::Base, vec![], vec![], None);
+        assert_eq!(synthetic.book().get_depth(), (vec![], vec![]));
+    }
+}
@@ -1,11 +1,54 @@
-#[derive(Debug)]
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CoreError, CoreResult};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Side {
     Bid,
     Ask,
 
 }
 
+impl Side {
+    pub fn is_ask(&self) -> bool {
+        matches!(self, Side::Ask)
+    }
+}
+
+/// How `group_prices_with` moves prices onto bucket boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GroupingRounding {
+    /// Asks rounded up and bids rounded down, so buckets never look better than the real book
+    /// and grouped asks always stay above grouped bids
+    #[default]
+    Conservative,
+    /// Both sides rounded down
+    Floor,
+    /// Both sides rounded up
+    Ceil,
+}
+
+impl GroupingRounding {
+    fn rounds_up(&self, is_ask: bool) -> bool {
+        match self {
+            GroupingRounding::Conservative => is_ask,
+            GroupingRounding::Floor => false,
+            GroupingRounding::Ceil => true,
+        }
+    }
+
+    /// Bucket `price` falls into for buckets of `grouping_size`.
+    /// A size so small the bucket count overflows is `ArithmeticOverflow`
+    pub fn bucket(&self, price: Decimal, grouping_size: Decimal, is_ask: bool) -> CoreResult<Decimal> {
+        let buckets = price
+            .checked_div(grouping_size)
+            .ok_or_else(|| CoreError::ArithmeticOverflow(format!("{} / {} overflows", price, grouping_size)))?;
+        let buckets = if self.rounds_up(is_ask) { buckets.ceil() } else { buckets.floor() };
+        Ok(buckets * grouping_size)
+    }
+}
+
 
 #[derive(Debug)]
 pub enum OrderStatus {
@@ -1,4 +1,6 @@
 use rust_decimal::Decimal;
+use core_pkg::error::CoreResult;
+use core_pkg::orderbook::{DeltaOutcome, OrderBook, OrderBookDelta, ReplayMessage, DEFAULT_CHECKSUM_DEPTH};
 
 type PriceLevel = (Decimal, Decimal);
 
@@ -6,14 +8,70 @@ type PriceLevel = (Decimal, Decimal);
 pub struct OrderbookEntity {
     pub asks: Vec<PriceLevel>,
     pub bids: Vec<PriceLevel>,
+    // Sequence range covered by the message. A snapshot only sets `last_sequence`
+    pub first_sequence: Option<u64>,
+    pub last_sequence: Option<u64>,
+    // CRC32 of the top levels after the update, as published by the exchange
+    pub checksum: Option<u32>,
 }
 
 impl OrderbookEntity {
     pub fn new(asks: Vec<PriceLevel>, bids: Vec<PriceLevel>) -> Self {
         Self {
             asks,
-            bids
+            bids,
+            first_sequence: None,
+            last_sequence: None,
+            checksum: None,
         }
     }
-}
 
+    pub fn with_sequence(mut self, first_sequence: u64, last_sequence: u64) -> Self {
+        self.first_sequence = Some(first_sequence);
+        self.last_sequence = Some(last_sequence);
+        self
+    }
+
+    pub fn with_checksum(mut self, checksum: u32) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    // The message as written by a recorder, replayed the same way `apply_to` applies it
+    pub fn to_replay_message(&self) -> ReplayMessage {
+        let (asks, bids) = (self.asks.clone(), self.bids.clone());
+        match self.last_sequence {
+            Some(last_sequence) => ReplayMessage::Delta {
+                first_sequence: self.first_sequence.unwrap_or(last_sequence),
+                last_sequence,
+                asks,
+                bids,
+            },
+            None => ReplayMessage::Update { asks, bids },
+        }
+    }
+
+    // Apply the update to the book.
+    // Returns the delta outcome and whether the book still matches the published checksum,
+    // or the error of a level the book rejected
+    pub fn apply_to(self, orderbook: &mut OrderBook) -> CoreResult<(DeltaOutcome, bool)> {
+        let checksum = self.checksum;
+        // messages without a sequence are applied as they come
+        let outcome = match self.last_sequence {
+            Some(last_sequence) => {
+                let first_sequence = self.first_sequence.unwrap_or(last_sequence);
+                orderbook.apply_delta(OrderBookDelta::new(first_sequence, last_sequence, self.asks, self.bids))
+            }
+            None => {
+                orderbook.update_order(true, self.asks)?;
+                orderbook.update_order(false, self.bids)?;
+                DeltaOutcome::Applied
+            }
+        };
+        let checksum_matched = match (outcome, checksum) {
+            (DeltaOutcome::Applied, Some(checksum)) => orderbook.verify_checksum(checksum, DEFAULT_CHECKSUM_DEPTH),
+            _ => true,
+        };
+        Ok((outcome, checksum_matched))
+    }
+}
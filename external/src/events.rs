@@ -0,0 +1,36 @@
+use core_pkg::orderbook::{BookEvent, OrderBook};
+use futures_util::Stream;
+use tokio::sync::broadcast;
+
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// A `BookEvent` tagged with the pair whose book emitted it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBookEvent {
+    pub symbol: String,
+    pub event: BookEvent,
+}
+
+pub type EventSender = broadcast::Sender<OrderBookEvent>;
+
+// Forward what the book recorded since the last call, events are dropped when nobody listens
+pub(crate) fn publish_events(sender: &EventSender, orderbook: &mut OrderBook) {
+    for event in orderbook.drain_events() {
+        // only fails when there is no receiver
+        let _ = sender.send(OrderBookEvent { symbol: orderbook.symbol.clone(), event });
+    }
+}
+
+// A receiver as a `Stream`. A consumer too slow for the channel skips to the newest events,
+// it should re-read the depth when it cares about every level
+pub(crate) fn event_stream(receiver: broadcast::Receiver<OrderBookEvent>) -> impl Stream<Item = OrderBookEvent> {
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
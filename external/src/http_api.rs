@@ -17,6 +17,8 @@ struct ApiResponse {
 struct OrderbookData {
     asks: Vec<(String, String)>,
     bids: Vec<(String, String)>,
+    #[serde(default)]
+    sequence: Option<u64>,
 }
 
 #[async_trait]
@@ -37,6 +39,11 @@ impl HttpApi {
             symbol: symbol.to_string(),
         }
     }
+
+    // Same endpoint, another market
+    pub fn for_symbol(&self, symbol: &str) -> Self {
+        HttpApi::new(&self.base_url, symbol)
+    }
 }
 
 #[async_trait]
@@ -61,7 +68,13 @@ impl OrderBookApi for HttpApi {
             )
         }).collect();
 
-        Ok(OrderbookEntity { asks, bids })
+        Ok(OrderbookEntity {
+            asks,
+            bids,
+            first_sequence: None,
+            last_sequence: response.data.sequence,
+            checksum: None,
+        })
     }
 
     fn switch_symbol(&mut self, symbol: &str) {
@@ -2,67 +2,237 @@ mod types;
 mod entities;
 mod http_api;
 mod ws_api;
+mod events;
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use tokio::sync::{ Mutex};
+use futures_util::Stream;
+use tokio::sync::{broadcast, Mutex};
 use types::LockedOrderBook;
 use crate::{http_api::*, ws_api::*};
-use core_pkg::orderbook::OrderBook;
+use core_pkg::orderbook::{BandLiquidity, BookHealth, DeltaOutcome, GroupingRounding, OrderBook, PriceLevel, ReplayMessage};
+use rust_decimal::Decimal;
 
 use crate::{entities::OrderbookEntity};
+use crate::events::{publish_events, EventSender, EVENT_CHANNEL_CAPACITY};
+pub use crate::events::OrderBookEvent;
+pub use crate::ws_api::SharedRecorder;
 
 pub struct OrderBookManager {
     http_api: HttpApi,
     ws_api: Option<OrderBookWebSocket>,
-    orderbook: LockedOrderBook,
+    // one book per pair, the websocket feeds the active one
+    orderbooks: HashMap<String, LockedOrderBook>,
+    symbol: String,
+    events: EventSender,
+    // REST snapshots and websocket messages are written here for replay
+    recorder: Option<SharedRecorder>,
 }
 
+// Methods taking a `symbol` route to that pair's book, `None` means the active pair
 impl OrderBookManager {
     // Pass empty ws_url to disable use Rust socket, which is not yet ready to use
     pub async fn new(api_url: &str, ws_url: &str, symbol: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::build(api_url, ws_url, symbol, None).await
+    }
+
+    // Same as `new`, recording every snapshot and update so a session can be replayed with `OrderBookReplayer`
+    pub async fn with_recorder(api_url: &str, ws_url: &str, symbol: &str, recorder: SharedRecorder) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::build(api_url, ws_url, symbol, Some(recorder)).await
+    }
+
+    async fn build(api_url: &str, ws_url: &str, symbol: &str, recorder: Option<SharedRecorder>) -> Result<Self, Box<dyn std::error::Error>> {
         let http_api = HttpApi::new(api_url, symbol);
-        let orderbook = Arc::new(Mutex::new(OrderBook::new()));
+        let orderbook = new_orderbook(symbol);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
         let mut ws_api = None;
 
         if ws_url.to_string().len() > 0 {
-            ws_api = Some(OrderBookWebSocket::new(ws_url.to_string(), symbol.to_string(), Arc::clone(&orderbook)));
+            let mut ws = OrderBookWebSocket::new(ws_url.to_string(), symbol.to_string(), Arc::clone(&orderbook))
+                .with_snapshot_api(HttpApi::new(api_url, symbol))
+                .with_event_sender(events.clone());
+            if let Some(recorder) = &recorder {
+                ws = ws.with_recorder(Arc::clone(recorder));
+            }
+            ws_api = Some(ws);
         }
         let mut manager = OrderBookManager {
             http_api,
             ws_api,
-            orderbook,
+            orderbooks: HashMap::from([(symbol.to_string(), orderbook)]),
+            symbol: symbol.to_string(),
+            events,
+            recorder,
         };
-        manager.fetch_and_fill_orderbook().await?;
+        manager.fetch_and_fill_orderbook(symbol).await?;
         if let Some(ws_api) = manager.ws_api.as_mut() {
             ws_api.connect_and_subscribe().await?;
         }
         Ok(manager)
     }
 
-    async fn fetch_and_fill_orderbook(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let orderbook_data = self.http_api.fetch_order_book().await.expect("Fetch REST orderbook failed");
-        let mut orderbook = self.orderbook.lock().await;
-        orderbook.initialize(orderbook_data.asks, orderbook_data.bids);
+    async fn fetch_and_fill_orderbook(&self, symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let orderbook = self.orderbook(Some(symbol))?;
+        let orderbook_data = self.http_api.for_symbol(symbol).fetch_order_book().await.expect("Fetch REST orderbook failed");
+        if let Some(recorder) = &self.recorder {
+            let message = ReplayMessage::Snapshot {
+                asks: orderbook_data.asks.clone(),
+                bids: orderbook_data.bids.clone(),
+                sequence: orderbook_data.last_sequence,
+            };
+            record_message(recorder, symbol, message).await;
+        }
+        let mut orderbook = orderbook.lock().await;
+        orderbook.initialize(orderbook_data.asks, orderbook_data.bids, orderbook_data.last_sequence);
+        publish_events(&self.events, &mut orderbook);
         Ok(())
     }
 
+    pub fn orderbook(&self, symbol: Option<&str>) -> Result<LockedOrderBook, Box<dyn std::error::Error>> {
+        let symbol = symbol.unwrap_or(&self.symbol);
+        let orderbook = self.orderbooks.get(symbol).ok_or_else(|| format!("Order book {} not found", symbol))?;
+        Ok(Arc::clone(orderbook))
+    }
+
+    // Level, top of book and snapshot changes of every pair, as they are applied
+    pub fn subscribe(&self) -> broadcast::Receiver<OrderBookEvent> {
+        self.events.subscribe()
+    }
+
+    // Same as `subscribe` as a `Stream`, lagging consumers skip to the newest events
+    pub fn event_stream(&self) -> impl Stream<Item = OrderBookEvent> {
+        events::event_stream(self.events.subscribe())
+    }
+
+    pub fn active_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    // Symbols with a book, sorted
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.orderbooks.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    // Track another pair from a REST snapshot, without touching the active one.
+    // Its book is only kept up to date through `update_orderbook`
+    pub async fn add_pair(&mut self, symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.orderbooks
+            .entry(symbol.to_string())
+            .or_insert_with(|| new_orderbook(symbol));
+        self.fetch_and_fill_orderbook(symbol).await
+    }
+
+    // The active pair cannot be removed, switch to another pair first
+    pub fn remove_pair(&mut self, symbol: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        if symbol == self.symbol {
+            return Err(format!("Cannot remove the active pair {}", symbol).into());
+        }
+        Ok(self.orderbooks.remove(symbol).is_some())
+    }
+
+    // Make `new_symbol` the active pair, the previous book stays in the registry
     pub async fn switch_pair(&mut self, new_symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
         self.http_api.switch_symbol(new_symbol);
-        self.fetch_and_fill_orderbook().await?;
+        self.add_pair(new_symbol).await?;
+        self.symbol = new_symbol.to_string();
+        let orderbook = self.orderbook(None)?;
         if let Some(ws_api) = &mut self.ws_api {
+            ws_api.orderbook = orderbook;
             ws_api.switch_pair(new_symbol.to_string()).await?;
         }
         Ok(())
     }
 
     // If you disable socket connection, you should connect to the enpoint outside
-    // then use this method to update the orderbook data
-    pub async fn update_orderbook(&mut self, updated_data: OrderbookEntity) {
-        let mut orderbook = self.orderbook.lock().await;
-        orderbook.update_order(true, updated_data.asks);
-        orderbook.update_order(false, updated_data.bids);
+    // then use this method to update the orderbook data.
+    // Sequenced updates are checked for gaps and checksums, if either fails or the book ends up
+    // locked / crossed the book is resynced from REST
+    pub async fn update_orderbook(&mut self, updated_data: OrderbookEntity, symbol: Option<&str>) -> Result<DeltaOutcome, Box<dyn std::error::Error>> {
+        let symbol = symbol.unwrap_or(&self.symbol).to_string();
+        if let Some(recorder) = &self.recorder {
+            record_message(recorder, &symbol, updated_data.to_replay_message()).await;
+        }
+        let (outcome, checksum_matched, health) = {
+            let orderbook = self.orderbook(Some(&symbol))?;
+            let mut orderbook = orderbook.lock().await;
+            let (outcome, checksum_matched) = match updated_data.apply_to(&mut orderbook) {
+                Ok(applied) => applied,
+                Err(e) => {
+                    // part of the message may be in the book, resync as for a checksum mismatch
+                    println!("orderbook {} rejected update: {}", symbol, e);
+                    (DeltaOutcome::Applied, false)
+                }
+            };
+            publish_events(&self.events, &mut orderbook);
+            (outcome, checksum_matched, orderbook.health())
+        };
+        if outcome.is_gap() || !checksum_matched || !health.is_healthy() {
+            self.fetch_and_fill_orderbook(&symbol).await?;
+        }
+        Ok(outcome)
+    }
+
+    pub async fn health(&self, symbol: Option<&str>) -> Result<BookHealth, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.health())
     }
+
+    // Force a REST resnapshot when the local book is locked or crossed, returns whether it did
+    pub async fn resync_if_unhealthy(&self, symbol: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.health(symbol).await?.is_healthy() {
+            return Ok(false);
+        }
+        self.fetch_and_fill_orderbook(symbol.unwrap_or(&self.symbol)).await?;
+        Ok(true)
+    }
+
+    pub async fn compute_dry(&self, fill_amount: Decimal, fill_by_quote: bool, is_buy: bool, symbol: Option<&str>) -> Result<(Decimal, Decimal, Decimal), Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.compute_dry(fill_amount, fill_by_quote, is_buy)?)
+    }
+
+    pub async fn get_depth(&self, symbol: Option<&str>) -> Result<(Vec<PriceLevel>, Vec<PriceLevel>), Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.get_depth())
+    }
+
+    pub async fn group_prices(&self, grouping_size: Decimal, rounding: GroupingRounding, symbol: Option<&str>) -> Result<(Vec<PriceLevel>, Vec<PriceLevel>), Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.group_prices_with(grouping_size, rounding)?)
+    }
+
+    pub async fn spread(&self, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.spread())
+    }
+
+    pub async fn spread_bps(&self, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.spread_bps())
+    }
+
+    pub async fn mid_price(&self, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.mid_price())
+    }
+
+    pub async fn microprice(&self, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.microprice())
+    }
+
+    pub async fn imbalance_top_n(&self, levels: usize, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.imbalance_top_n(levels))
+    }
+
+    pub async fn imbalance_within_pct(&self, pct: Decimal, symbol: Option<&str>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.imbalance_within_pct(pct))
+    }
+
+    pub async fn liquidity_within_bands(&self, bands: &[Decimal], symbol: Option<&str>) -> Result<Option<Vec<BandLiquidity>>, Box<dyn std::error::Error>> {
+        Ok(self.orderbook(symbol)?.lock().await.liquidity_within_bands(bands))
+    }
+}
+
+// Books of the manager record their changes so they can be published
+fn new_orderbook(symbol: &str) -> LockedOrderBook {
+    let mut orderbook = OrderBook::with_symbol(symbol);
+    orderbook.set_record_events(true);
+    Arc::new(Mutex::new(orderbook))
 }
 
 #[cfg(test)]
@@ -72,7 +242,7 @@ mod tests {
     #[tokio::test]
     async fn test_initialization_with_out_websocket() {
         let mut order_book_manager = OrderBookManager::new("https://apex.position.exchange", "", "BTCBUSD").await.unwrap();
-        let depth = order_book_manager.orderbook.lock().await.get_depth();
+        let depth = order_book_manager.get_depth(None).await.unwrap();
         println!("depth {:?}", depth);
         assert!(!depth.0.is_empty());
         assert!(!depth.1.is_empty());
@@ -82,8 +252,8 @@ mod tests {
         ], vec![
             (dec!(9), dec!(0.1)),
             (dec!(8), dec!(0.1)),
-        ])).await;
-        let depth = order_book_manager.orderbook.lock().await.get_depth();
+        ]), None).await.unwrap();
+        let depth = order_book_manager.get_depth(None).await.unwrap();
         println!("depth {:?}", depth);
     }
     
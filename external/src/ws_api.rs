@@ -5,21 +5,28 @@ use futures_util::{StreamExt, SinkExt};
 use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use core_pkg::orderbook::OrderBook;
+use core_pkg::orderbook::{DeltaOutcome, OrderBook, OrderBookRecorder, ReplayMessage};
 use warp::http::Request;
 
-use crate::{entities::OrderbookEntity, types::LockedOrderBook};
+use crate::{entities::OrderbookEntity, events::{publish_events, EventSender}, http_api::{HttpApi, OrderBookApi}, types::LockedOrderBook};
 
 type PriceLevel = (Decimal, Decimal);
 // pub type LockedOrderBook = Arc<Mutex<OrderBook>>;
 
 type LockWsStream = Arc<Mutex<WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>>>;
+pub type SharedRecorder = Arc<Mutex<OrderBookRecorder<Box<dyn std::io::Write + Send>>>>;
 
 pub struct OrderBookWebSocket {
     pub url: String,
     pub symbol: String,
     pub orderbook: LockedOrderBook,
     ws_stream: Option<LockWsStream>,
+    // REST api used to re-snapshot the book when it drifts from the exchange
+    snapshot_api: Option<HttpApi>,
+    // where the changes recorded by the book are published
+    event_sender: Option<EventSender>,
+    // raw book messages are written here for replay
+    recorder: Option<SharedRecorder>,
 }
 
 /*
@@ -38,9 +45,28 @@ impl OrderBookWebSocket {
             symbol,
             orderbook,
             ws_stream: None,
+            snapshot_api: None,
+            event_sender: None,
+            recorder: None,
         }
     }
 
+    pub fn with_snapshot_api(mut self, snapshot_api: HttpApi) -> Self {
+        self.snapshot_api = Some(snapshot_api);
+        self
+    }
+
+    pub fn with_event_sender(mut self, event_sender: EventSender) -> Self {
+        self.event_sender = Some(event_sender);
+        self
+    }
+
+    /// Record every snapshot and update received, see `OrderBookReplayer` to play them back
+    pub fn with_recorder(mut self, recorder: SharedRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     pub async fn connect_and_subscribe(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.connect().await.expect("Connect Socket Error");
         // let (mut write, mut read) = self.ws_stream.split();
@@ -56,6 +82,9 @@ impl OrderBookWebSocket {
     pub async fn switch_pair(&mut self, symbol: String) -> Result<(), Box<dyn std::error::Error>> {
         self.unsubscribe().await?;
         self.subscribe(symbol.clone()).await?;
+        if let Some(snapshot_api) = self.snapshot_api.as_mut() {
+            snapshot_api.switch_symbol(&symbol);
+        }
         self.symbol = symbol;
         Ok(())
     }
@@ -137,6 +166,7 @@ impl OrderBookWebSocket {
                 let msg = msg?;
                 if let Message::Text(text) = msg {
                     if let Some(updated_data) = self.parse_orderbook_update(&text) {
+                        self.record(updated_data.to_replay_message()).await;
                         self.update_orderbook(updated_data).await;
                     }
                 }
@@ -181,17 +211,85 @@ impl OrderBookWebSocket {
             .collect::<Result<Vec<PriceLevel>, _>>()
             .ok()?;
 
-        Some(OrderbookEntity { asks, bids })
+        // sequence range and checksum are optional, unsequenced updates are applied as they come
+        let first_sequence = orderbook_data.get("firstSequence").and_then(Value::as_u64);
+        let last_sequence = orderbook_data.get("lastSequence").and_then(Value::as_u64);
+        // exchanges publish the crc32 as a signed 32 bit integer
+        let checksum = orderbook_data.get("checksum").and_then(Value::as_i64).map(|checksum| checksum as u32);
+
+        Some(OrderbookEntity { asks, bids, first_sequence, last_sequence, checksum })
     }
 
 
-    async fn update_orderbook(&self, updated_data: OrderbookEntity) {
+    async fn update_orderbook(&self, updated_data: OrderbookEntity) -> DeltaOutcome {
+        let (outcome, checksum_matched, health) = {
+            let mut orderbook = self.orderbook.lock().await;
+            let (outcome, checksum_matched) = match updated_data.apply_to(&mut orderbook) {
+                Ok(applied) => applied,
+                Err(e) => {
+                    // part of the message may be in the book, resync as for a checksum mismatch
+                    println!("orderbook {} rejected update: {}", self.symbol, e);
+                    (DeltaOutcome::Applied, false)
+                }
+            };
+            if let Some(event_sender) = &self.event_sender {
+                publish_events(event_sender, &mut orderbook);
+            }
+            (outcome, checksum_matched, orderbook.health())
+        };
+
+        if let DeltaOutcome::Gap { expected, received } = outcome {
+            println!("orderbook {} sequence gap, expected {} received {}", self.symbol, expected, received);
+        }
+        if !checksum_matched {
+            println!("orderbook {} checksum mismatch", self.symbol);
+        }
+        if !health.is_healthy() {
+            println!("orderbook {} is {:?}", self.symbol, health);
+        }
+        if outcome.is_gap() || !checksum_matched || !health.is_healthy() {
+            if let Err(e) = self.resync_orderbook().await {
+                println!("Error resyncing orderbook {}: {}", self.symbol, e);
+            }
+        }
+        outcome
+    }
+
+    async fn record(&self, message: ReplayMessage) {
+        if let Some(recorder) = &self.recorder {
+            record_message(recorder, &self.symbol, message).await;
+        }
+    }
+
+    // Replace the local book with a fresh REST snapshot
+    async fn resync_orderbook(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot_api = self.snapshot_api.as_ref().ok_or("Snapshot api is not set")?;
+        let orderbook_data = snapshot_api.fetch_order_book().await?;
+        self.record(ReplayMessage::Snapshot {
+            asks: orderbook_data.asks.clone(),
+            bids: orderbook_data.bids.clone(),
+            sequence: orderbook_data.last_sequence,
+        })
+        .await;
         let mut orderbook = self.orderbook.lock().await;
-        orderbook.update_order(true, updated_data.asks);
-        orderbook.update_order(false, updated_data.bids);
+        orderbook.initialize(orderbook_data.asks, orderbook_data.bids, orderbook_data.last_sequence);
+        if let Some(event_sender) = &self.event_sender {
+            publish_events(event_sender, &mut orderbook);
+        }
+        Ok(())
     }
 }
 
+// Append `message` stamped with the current time, a failing recorder is only logged
+pub(crate) async fn record_message(recorder: &SharedRecorder, symbol: &str, message: ReplayMessage) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    if let Err(e) = recorder.lock().await.record(timestamp, symbol, message) {
+        println!("Error recording orderbook {}: {}", symbol, e);
+    }
+}
 
 async fn connect_to_socket_io_and_get_url(url: &str, path: &str) -> Result<String, Box<dyn std::error::Error>> {
     let url = Url::parse(url)?;
@@ -233,6 +331,7 @@ mod tests {
     use super::*;
     use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
+    use core_pkg::orderbook::{BookEvent, BookHealth, CrossPolicy, OrderBookReplayer, Side};
     use tokio_tungstenite::tungstenite::protocol::Message;
     use warp::{Filter, ws::WebSocket};
     async fn order_book_update_ws(ws: WebSocket) {
@@ -249,14 +348,14 @@ mod tests {
         //     bids: vec![(Decimal::new(99, 1), Decimal::new(1, 0))],
         // };
         let mut orderbook = OrderBook::new();
-        orderbook.initialize(vec![(dec!(100.0), dec!(1))], vec![(dec!(99.0), dec!(1.0))]);
+        orderbook.initialize(vec![(dec!(100.0), dec!(1))], vec![(dec!(99.0), dec!(1.0))], None);
         let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "Hello".to_string(), Arc::new(Mutex::new(orderbook)));
 
         // Update the order book
-        let updated_data = OrderbookEntity {
-            asks: vec![((dec!(101), dec!(1)))],
-            bids: vec![((dec!(99), dec!(0)))],
-        };
+        let updated_data = OrderbookEntity::new(
+            vec![((dec!(101), dec!(1)))],
+            vec![((dec!(99), dec!(0)))],
+        );
         orderbook_websocket.update_orderbook(updated_data.clone()).await;
 
         // Check if the order book is updated
@@ -273,6 +372,120 @@ mod tests {
         // assert_eq!(updated_orderbook.bids, updated_data.bids);
     }
 
+    #[tokio::test]
+    async fn test_update_orderbook_sequence_gap() {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(vec![(dec!(100), dec!(1))], vec![(dec!(99), dec!(1))], Some(5));
+        let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "fBTCBUSD".to_string(), Arc::new(Mutex::new(orderbook)));
+
+        let message = r#"42["orderBookUpdated",{"room":"orderBook@fBTCBUSD","event":"orderBookUpdated","data":{"symbol":"fBTCBUSD","asks":[["100.1","0.5"]],"bids":[],"firstSequence":6,"lastSequence":7}}]"#;
+        let updated_data = orderbook_websocket.parse_orderbook_update(message).unwrap();
+        assert_eq!(updated_data.first_sequence, Some(6));
+        assert_eq!(updated_data.last_sequence, Some(7));
+        assert_eq!(orderbook_websocket.update_orderbook(updated_data).await, DeltaOutcome::Applied);
+
+        let missed = OrderbookEntity::new(vec![(dec!(100.2), dec!(1))], vec![]).with_sequence(9, 9);
+        assert_eq!(orderbook_websocket.update_orderbook(missed).await, DeltaOutcome::Gap { expected: 8, received: 9 });
+        assert_eq!(orderbook_websocket.orderbook.lock().await.sequence, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_update_orderbook_checksum() {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(vec![(dec!(101), dec!(1))], vec![(dec!(99), dec!(1))], None);
+        let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "fBTCBUSD".to_string(), Arc::new(Mutex::new(orderbook)));
+
+        // crc32("99:1:100:3:101:1") published as a signed integer
+        let message = r#"42["orderBookUpdated",{"room":"orderBook@fBTCBUSD","event":"orderBookUpdated","data":{"symbol":"fBTCBUSD","asks":[["100","3"]],"bids":[],"checksum":-1645676350}}]"#;
+        let updated_data = orderbook_websocket.parse_orderbook_update(message).unwrap();
+        assert_eq!(updated_data.checksum, Some(-1645676350i32 as u32));
+
+        let mut local = orderbook_websocket.orderbook.lock().await;
+        let (outcome, checksum_matched) = updated_data.clone().apply_to(&mut local).unwrap();
+        assert_eq!(outcome, DeltaOutcome::Applied);
+        assert!(checksum_matched);
+
+        let (_, checksum_matched) = updated_data.with_checksum(1).apply_to(&mut local).unwrap();
+        assert!(!checksum_matched);
+    }
+
+    #[tokio::test]
+    async fn test_update_orderbook_crossed() {
+        let mut orderbook = OrderBook::new();
+        orderbook.initialize(vec![(dec!(101), dec!(1))], vec![(dec!(99), dec!(1))], None);
+        let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "fBTCBUSD".to_string(), Arc::new(Mutex::new(orderbook)));
+
+        // the removal of the 99 bid got lost, no snapshot api so the book stays flagged
+        let updated_data = OrderbookEntity::new(vec![(dec!(98.5), dec!(1))], vec![]);
+        assert_eq!(orderbook_websocket.update_orderbook(updated_data).await, DeltaOutcome::Applied);
+        assert_eq!(orderbook_websocket.orderbook.lock().await.health(), BookHealth::Crossed);
+
+        orderbook_websocket.orderbook.lock().await.cross_policy = CrossPolicy::PruneStale;
+        let updated_data = OrderbookEntity::new(vec![(dec!(98.5), dec!(2))], vec![]);
+        orderbook_websocket.update_orderbook(updated_data).await;
+        let orderbook = orderbook_websocket.orderbook.lock().await;
+        assert_eq!(orderbook.health(), BookHealth::Healthy);
+        assert!(orderbook.bids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_orderbook_publishes_events() {
+        let mut orderbook = OrderBook::with_symbol("fBTCBUSD");
+        orderbook.initialize(vec![(dec!(100), dec!(1))], vec![(dec!(99), dec!(1))], None);
+        orderbook.set_record_events(true);
+        let (event_sender, _) = tokio::sync::broadcast::channel(16);
+        let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "fBTCBUSD".to_string(), Arc::new(Mutex::new(orderbook)))
+            .with_event_sender(event_sender.clone());
+        let mut events = Box::pin(crate::events::event_stream(event_sender.subscribe()));
+
+        orderbook_websocket.update_orderbook(OrderbookEntity::new(vec![(dec!(99.5), dec!(2))], vec![])).await;
+
+        let event = events.next().await.unwrap();
+        assert_eq!(event.symbol, "fBTCBUSD");
+        assert_eq!(event.event, BookEvent::LevelAdded { side: Side::Ask, price: dec!(99.5), quantity: dec!(2) });
+        let event = events.next().await.unwrap();
+        assert_eq!(event.event, BookEvent::BestBidAskChanged { best_ask: Some(dec!(99.5)), best_bid: Some(dec!(99)) });
+    }
+
+    // Recorder sink the test can read back
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recorded_messages_replay() {
+        let mut orderbook = OrderBook::with_symbol("fBTCBUSD");
+        orderbook.initialize(vec![(dec!(100), dec!(1))], vec![(dec!(99), dec!(1))], Some(5));
+        let buffer = SharedBuffer::default();
+        let recorder: SharedRecorder = Arc::new(Mutex::new(OrderBookRecorder::new(Box::new(buffer.clone()))));
+        let orderbook_websocket = OrderBookWebSocket::new(String::from(""), "fBTCBUSD".to_string(), Arc::new(Mutex::new(orderbook)))
+            .with_recorder(recorder);
+
+        orderbook_websocket
+            .record(ReplayMessage::Snapshot { asks: vec![(dec!(100), dec!(1))], bids: vec![(dec!(99), dec!(1))], sequence: Some(5) })
+            .await;
+        let message = r#"42["orderBookUpdated",{"room":"orderBook@fBTCBUSD","event":"orderBookUpdated","data":{"symbol":"fBTCBUSD","asks":[["100.1","0.5"]],"bids":[["99","0"]],"firstSequence":6,"lastSequence":7}}]"#;
+        let updated_data = orderbook_websocket.parse_orderbook_update(message).unwrap();
+        orderbook_websocket.record(updated_data.to_replay_message()).await;
+        orderbook_websocket.update_orderbook(updated_data).await;
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let mut replayer = OrderBookReplayer::from_reader(recording.as_slice(), "fBTCBUSD").unwrap();
+        let replayed = replayer.seek_sequence(7).unwrap().clone();
+        let live = orderbook_websocket.orderbook.lock().await;
+        assert_eq!(replayed.get_depth(), live.get_depth());
+        assert_eq!(replayed.sequence, Some(7));
+    }
+
     #[tokio::test]
     async fn test_websocket() {
         // Prepare the mock WebSocket server
@@ -285,12 +498,12 @@ mod tests {
         tokio::spawn(server.1);
 
         // Prepare the initial order book
-        let initial_orderbook = OrderbookEntity {
-            asks: vec![(Decimal::new(100, 1), Decimal::new(1, 0))],
-            bids: vec![(Decimal::new(99, 1), Decimal::new(1, 0))],
-        };
+        let initial_orderbook = OrderbookEntity::new(
+            vec![(Decimal::new(100, 1), Decimal::new(1, 0))],
+            vec![(Decimal::new(99, 1), Decimal::new(1, 0))],
+        );
         let mut orderbook = OrderBook::new();
-        orderbook.initialize(initial_orderbook.asks, initial_orderbook.bids);
+        orderbook.initialize(initial_orderbook.asks, initial_orderbook.bids, None);
 
         // Initialize the OrderBookWebSocket with the mock server URL
         let url = "ws://127.0.0.1:3030/socket.io/?EIO=4&transport=websocket";
@@ -1,9 +1,10 @@
-use serde::{ser::SerializeTuple, Serialize};
 use serde_wasm_bindgen::to_value;
 use wasm_bindgen::prelude::*;
-use core::orderbook::OrderBook;
-use std::{collections::HashMap, sync::{Arc, Mutex}, cell::RefCell, rc::Rc};
-use rust_decimal::Decimal;
+use core::compute::{order::OpenOrderRequest, position::Position};
+use core::error::CoreError;
+use core::orderbook::{BookEvent, DepthPoint, GroupingRounding, MarketSpec, OrderBook, OrderBookRecorder, OrderBookRegistry, OrderBookSnapshot, PriceCap, ReplayMessage};
+use std::{cell::{Cell, Ref, RefCell}, rc::Rc};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use rust_decimal_macros::dec;
 use console_error_panic_hook::set_once;
 use js_sys::Array;
@@ -26,7 +27,13 @@ type PriceLevel = (Decimal, Decimal);
 
 #[wasm_bindgen]
 pub struct OrderBookManager {
-    orderbook: Rc<RefCell<OrderBook>>,
+    // one book per pair, calls without a symbol go to the active pair
+    orderbooks: Rc<RefCell<OrderBookRegistry>>,
+    // callbacks notified of book changes, by listener id
+    listeners: Rc<RefCell<Vec<(u32, js_sys::Function)>>>,
+    next_listener_id: Cell<u32>,
+    // messages fed to `initialize_orders` / `update_orders` while recording
+    recorder: RefCell<Option<OrderBookRecorder<Vec<u8>>>>,
     // order_manager: Arc::<Mutex::<order::OrderManager>>,
     order_manager: Rc<RefCell<order::OrderManager>>,
 }
@@ -36,39 +43,98 @@ impl OrderBookManager {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
         Self {
-            orderbook: Rc::new(RefCell::new(OrderBook::new())),
+            orderbooks: Rc::new(RefCell::new(OrderBookRegistry::new())),
+            listeners: Rc::new(RefCell::new(vec![])),
+            next_listener_id: Cell::new(0),
+            recorder: RefCell::new(None),
             // order_manager: Arc::new(Mutex::new(order::OrderManager::new())),
             order_manager: Rc::new(RefCell::new(order::OrderManager::new())),
         }
     }
 
     #[wasm_bindgen]
-    pub fn initialize_orders(&self, asks: Array, bids: Array) {
-        let asks: Vec<PriceLevel> = to_price_level_vec(&asks)
-            .into_iter()
-            .map(|(price, quantity)| (Decimal::from_str_exact(&price).unwrap(), Decimal::from_str_exact(&quantity).unwrap()))
-            .collect();
+    pub fn initialize_orders(&self, asks: Array, bids: Array, symbol: Option<String>) -> Result<(), JsValue> {
+        let asks = parse_price_levels(&asks)?;
+        let bids = parse_price_levels(&bids)?;
 
-        let bids: Vec<PriceLevel> = to_price_level_vec(&bids)
-            .into_iter()
-            .map(|(price, quantity)| (Decimal::from_str_exact(&price).unwrap(), Decimal::from_str_exact(&quantity).unwrap()))
-            .collect();
+        self.record(&symbol, || ReplayMessage::Snapshot { asks: asks.clone(), bids: bids.clone(), sequence: None });
+        self.write_orderbook(symbol, |orderbook| orderbook.initialize(asks, bids, None));
+        Ok(())
+    }
 
-        self.orderbook.borrow_mut().initialize(asks, bids);
+    #[wasm_bindgen]
+    pub fn update_orders(&self, is_ask: bool, updates: Array, symbol: Option<String>) -> Result<(), JsValue> {
+        let updates = parse_price_levels(&updates)?;
+
+        // rejected batches never reach the book, so they are not recorded either
+        let recorded = updates.clone();
+        self.write_orderbook(symbol.clone(), |orderbook| orderbook.update_order(is_ask, updates))
+            .map_err(core_error)?;
+        self.record(&symbol, || side_update(is_ask, recorded));
+        Ok(())
     }
 
+    /// Start recording the messages given to `initialize_orders` and `update_orders`,
+    /// dropping a recording in progress
     #[wasm_bindgen]
-    pub fn update_orders(&self, is_ask: bool, updates: Array) {
-        let updates: Vec<PriceLevel> = to_price_level_vec(&updates)
-            .into_iter()
-            .map(|(price, quantity)| (Decimal::from_str_exact(&price).unwrap(), Decimal::from_str_exact(&quantity).unwrap()))
-            .collect();
+    pub fn start_recording(&self) {
+        *self.recorder.borrow_mut() = Some(OrderBookRecorder::new(vec![]));
+    }
 
-        self.orderbook.borrow_mut().update_order(is_ask, updates);
+    /// Stop recording and return the messages of every pair as newline delimited JSON,
+    /// to be replayed with one `OrderBookReplayer` per symbol
+    /// @returns {string}
+    #[wasm_bindgen]
+    pub fn stop_recording(&self) -> Result<String, JsValue> {
+        let recorder = self.recorder.borrow_mut().take().ok_or_else(|| JsValue::from_str("Not recording"))?;
+        String::from_utf8(recorder.into_inner()).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Like `update_orders` but rejects the whole batch when a level is off the tick or lot grid
+    /// of the market spec, see `set_market_spec`
+    #[wasm_bindgen]
+    pub fn try_update_orders(&self, is_ask: bool, updates: Array, symbol: Option<String>) -> Result<(), JsValue> {
+        let updates = parse_price_levels(&updates)?;
+
+        // rejected batches never reach the book, so they are not recorded either
+        let recorded = updates.clone();
+        self.write_orderbook(symbol.clone(), |orderbook| orderbook.try_update_order(is_ask, updates))
+            .map_err(core_error)?;
+        self.record(&symbol, || side_update(is_ask, recorded));
+        Ok(())
+    }
+
+    /// Set the tick and lot sizes of the book. Precisions default to the decimals of the sizes.
+    /// Levels are then normalized ("100.1" and "100.10" alike), `compute_dry` and `group_prices`
+    /// results are rounded to valid increments
+    #[wasm_bindgen]
+    pub fn set_market_spec(
+        &self,
+        tick_size: String,
+        lot_size: String,
+        price_precision: Option<u32>,
+        quantity_precision: Option<u32>,
+        symbol: Option<String>,
+    ) -> Result<(), JsValue> {
+        let tick_size = Decimal::from_str_exact(&tick_size).map_err(|e| core_error(e.into()))?;
+        let lot_size = Decimal::from_str_exact(&lot_size).map_err(|e| core_error(e.into()))?;
+        let spec = MarketSpec::new(tick_size, lot_size).and_then(|spec| {
+            MarketSpec::with_precision(
+                tick_size,
+                lot_size,
+                price_precision.unwrap_or(spec.price_precision),
+                quantity_precision.unwrap_or(spec.quantity_precision),
+            )
+        });
+        let spec = spec.map_err(core_error)?;
+        self.write_orderbook(symbol, |orderbook| orderbook.set_market_spec(spec));
+        Ok(())
     }
 
     
-    /// Compute the average price, total base amount, slippage for a given fill amount
+    /// Compute the average price, total base amount, slippage for a given fill amount.
+    /// Throws an `Error` with code `EMPTY_BOOK` or `INSUFFICIENT_LIQUIDITY` when the book cannot fill
+    /// the amount, `INVALID_INPUT` for a non positive amount or one below the lot size
     ///
     /// # Returns
     /// * `avg_price`: average price of the fill
@@ -82,12 +148,13 @@ impl OrderBookManager {
         fill_amount: String,
         fill_by_quote: bool,
         is_buy: bool,
+        symbol: Option<String>,
     ) -> Result<JsValue, JsValue> {
         let fill_amount_decimal =
-            Decimal::from_str_exact(&fill_amount).map_err(|e| JsValue::from_str(&e.to_string())).unwrap();
+            Decimal::from_str_exact(&fill_amount).map_err(|e| core_error(e.into()))?;
 
         let (avg_price, total_base, slippage) =
-            self.orderbook.borrow().compute_dry(fill_amount_decimal, fill_by_quote, is_buy);
+            self.orderbook(symbol)?.compute_dry(fill_amount_decimal, fill_by_quote, is_buy).map_err(core_error)?;
 
         // let result = ComputeDryResult {
         //     avg_price: avg_price.to_string(),
@@ -98,9 +165,212 @@ impl OrderBookManager {
         to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))
     }
 
+    /// `compute_dry` for several amounts at once, e.g. a slippage table for 1k, 10k and 100k notional.
+    /// An amount the book cannot fill is `null`, any other failure throws like `compute_dry`
+    /// @returns {Array} ([avg_price: string, total_base: string, slippage: string] | null)[], in the order of `fill_amounts`
+    #[wasm_bindgen]
+    pub fn compute_dry_batch(&self, fill_amounts: Array, fill_by_quote: bool, is_buy: bool, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let fill_amounts = fill_amounts
+            .iter()
+            .map(|fill_amount| {
+                let fill_amount = fill_amount.as_string().ok_or_else(|| JsValue::from_str("Fill amount must be a string"))?;
+                Decimal::from_str_exact(&fill_amount).map_err(|e| core_error(e.into()))
+            })
+            .collect::<Result<Vec<Decimal>, JsValue>>()?;
+
+        let result = self
+            .orderbook(symbol)?
+            .compute_dry_batch(&fill_amounts, fill_by_quote, is_buy)
+            .into_iter()
+            .map(|result| match result {
+                Ok((avg_price, total_base, slippage)) => Ok(Some((avg_price.to_string(), total_base.to_string(), slippage.to_string()))),
+                Err(CoreError::EmptyBook(_) | CoreError::InsufficientLiquidity(_)) => Ok(None),
+                Err(error) => Err(core_error(error)),
+            })
+            .collect::<Result<Vec<Option<(String, String, String)>>, JsValue>>()?;
+        to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// `compute_dry` with the taker fee included, for the all-in price of the order form.
+    /// Uses `taker_fee` (0.001 = 0.1%) when given, else the taker fee and base precision of the pair
+    /// set up with `new_pair_order_compute`. When `fill_by_quote` the amount is what is spent (buy)
+    /// or received (sell) fees included. Throws an `Error` with code `EMPTY_BOOK` or
+    /// `INSUFFICIENT_LIQUIDITY` when the book cannot fill the amount
+    ///
+    /// # Returns
+    /// * `effective_price`: average price including the fee
+    /// * `notional`: quote at book prices, fees excluded
+    /// * `net_quote`: quote paid on a buy, received on a sell, fees included
+    /// @returns {{
+    ///   avg_price: string,
+    ///   effective_price: string,
+    ///   filled_base: string,
+    ///   notional: string,
+    ///   net_quote: string,
+    ///   slippage: string,
+    ///   fee: { rate: string, amount: string, price_impact: string }
+    /// }}
     #[wasm_bindgen]
-    pub fn get_depth(&self) -> Result<JsValue, JsValue> {
-        let orderbook = self.orderbook.borrow();
+    pub fn compute_dry_with_fee(
+        &self,
+        fill_amount: String,
+        fill_by_quote: bool,
+        is_buy: bool,
+        taker_fee: Option<String>,
+        symbol: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let fill_amount_decimal = Decimal::from_str_exact(&fill_amount).map_err(|e| core_error(e.into()))?;
+        let symbol = self.route(symbol);
+        let orderbook = self.orderbook(Some(symbol.clone()))?;
+        let fill = match taker_fee {
+            Some(taker_fee) => {
+                let taker_fee_decimal = Decimal::from_str_exact(&taker_fee).map_err(|e| core_error(e.into()))?;
+                orderbook.compute_dry_with_fee(fill_amount_decimal, fill_by_quote, is_buy, taker_fee_decimal)
+            }
+            None => self
+                .order_manager
+                .borrow()
+                .get_order_compute(&symbol)?
+                .borrow()
+                .compute_dry_with_fees(&orderbook, fill_amount_decimal, fill_by_quote, is_buy),
+        }
+        .map_err(core_error)?;
+        to_value(&fill).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Walk the book for a market order and report what fills, including partial fills
+    ///
+    /// # Returns
+    /// * `is_complete`: whether the book had enough liquidity for the whole amount
+    /// * `unfilled`: amount left unfilled, in the unit of `fill_amount`
+    /// * `fills`: per level breakdown, best price first
+    /// @returns {{
+    ///   avg_price: string,
+    ///   filled_base: string,
+    ///   filled_quote: string,
+    ///   unfilled: string,
+    ///   worst_price: string | undefined,
+    ///   levels_consumed: number,
+    ///   slippage: string,
+    ///   fills: { price: string, base: string, quote: string }[],
+    ///   is_complete: boolean
+    /// }}
+    #[wasm_bindgen]
+    pub fn compute_fill(
+        &self,
+        fill_amount: String,
+        fill_by_quote: bool,
+        is_buy: bool,
+        symbol: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let fill_amount_decimal = Decimal::from_str_exact(&fill_amount).map_err(|e| core_error(e.into()))?;
+        let estimate = self.orderbook(symbol)?.compute_fill(fill_amount_decimal, fill_by_quote, is_buy).map_err(core_error)?;
+        to_value(&estimate).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Same as `compute_fill` but stops at a limit price, or at the last level within `max_deviation`
+    /// percent (1 = 1%) of the best price when no limit price is given. `max_deviation` bounds the worst
+    /// price, not the average slippage: use `max_fill_for_slippage` for that
+    /// @returns same shape as `compute_fill`
+    #[wasm_bindgen]
+    pub fn compute_capped_fill(
+        &self,
+        fill_amount: String,
+        fill_by_quote: bool,
+        is_buy: bool,
+        limit_price: Option<String>,
+        max_deviation: Option<String>,
+        symbol: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let fill_amount_decimal = Decimal::from_str_exact(&fill_amount).map_err(|e| core_error(e.into()))?;
+        let cap = match (limit_price, max_deviation) {
+            (Some(limit_price), _) => PriceCap::LimitPrice(Decimal::from_str_exact(&limit_price).map_err(|e| core_error(e.into()))?),
+            (None, Some(max_deviation)) => PriceCap::MaxPriceDeviation(Decimal::from_str_exact(&max_deviation).map_err(|e| core_error(e.into()))?),
+            (None, None) => return Err(JsValue::from_str("Either limit_price or max_deviation is required")),
+        };
+        let estimate = self.orderbook(symbol)?.compute_capped_fill(fill_amount_decimal, fill_by_quote, is_buy, cap).map_err(core_error)?;
+        to_value(&estimate).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Largest amount that can be filled before the slippage exceeds `max_slippage` (1 = 1%).
+    /// The amount is in base, or in quote when `fill_by_quote`
+    /// @returns {string}
+    #[wasm_bindgen]
+    pub fn max_fill_for_slippage(&self, max_slippage: String, fill_by_quote: bool, is_buy: bool, symbol: Option<String>) -> Result<String, JsValue> {
+        let max_slippage_decimal = Decimal::from_str_exact(&max_slippage).map_err(|e| core_error(e.into()))?;
+        Ok(self.orderbook(symbol)?.max_fill_for_slippage(max_slippage_decimal, fill_by_quote, is_buy).to_string())
+    }
+
+    /// Largest amount that can be filled without trading at a price worse than `price`.
+    /// The amount is in base, or in quote when `fill_by_quote`
+    /// @returns {string}
+    #[wasm_bindgen]
+    pub fn max_fill_before_price(&self, price: String, fill_by_quote: bool, is_buy: bool, symbol: Option<String>) -> Result<String, JsValue> {
+        let price_decimal = Decimal::from_str_exact(&price).map_err(|e| core_error(e.into()))?;
+        Ok(self.orderbook(symbol)?.max_fill_before_price(price_decimal, fill_by_quote, is_buy).to_string())
+    }
+
+    /// Best ask minus best bid, undefined when a side is empty
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn spread(&self, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        Ok(self.orderbook(symbol)?.spread().map(|spread| spread.to_string()))
+    }
+
+    /// Spread relative to mid in basis points, undefined when a side is empty
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn spread_bps(&self, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        Ok(self.orderbook(symbol)?.spread_bps().map(|spread_bps| spread_bps.to_string()))
+    }
+
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn mid_price(&self, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        Ok(self.orderbook(symbol)?.mid_price().map(|mid_price| mid_price.to_string()))
+    }
+
+    /// Mid weighted by the opposite top of book size
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn microprice(&self, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        Ok(self.orderbook(symbol)?.microprice().map(|microprice| microprice.to_string()))
+    }
+
+    /// Bid/ask volume imbalance over the best `levels` levels, from -1 (only asks) to 1 (only bids)
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn imbalance_top_n(&self, levels: usize, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        Ok(self.orderbook(symbol)?.imbalance_top_n(levels).map(|imbalance| imbalance.to_string()))
+    }
+
+    /// Bid/ask volume imbalance over the levels within `pct` percent (1 = 1%) of mid
+    /// @returns {string | undefined}
+    #[wasm_bindgen]
+    pub fn imbalance_within_pct(&self, pct: String, symbol: Option<String>) -> Result<Option<String>, JsValue> {
+        let pct_decimal = Decimal::from_str_exact(&pct).map_err(|e| core_error(e.into()))?;
+        Ok(self.orderbook(symbol)?.imbalance_within_pct(pct_decimal).map(|imbalance| imbalance.to_string()))
+    }
+
+    /// Quote notional resting within each band (percent around mid, 1 = 1%)
+    /// @param {string[]} bands
+    /// @returns {{ band: string, bid_notional: string, ask_notional: string }[] | undefined}
+    #[wasm_bindgen]
+    pub fn liquidity_within_bands(&self, bands: Array, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let bands = bands
+            .iter()
+            .map(|band| {
+                let band = band.as_string().ok_or_else(|| JsValue::from_str("Band must be a string"))?;
+                Decimal::from_str_exact(&band).map_err(|e| core_error(e.into()))
+            })
+            .collect::<Result<Vec<Decimal>, JsValue>>()?;
+        let liquidity = self.orderbook(symbol)?.liquidity_within_bands(&bands);
+        to_value(&liquidity).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_depth(&self, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let orderbook = self.orderbook(symbol)?;
         let (asks, bids) = orderbook.get_depth();
         let asks_js: Vec<(String, String)> = asks
             .into_iter()
@@ -118,39 +388,103 @@ impl OrderBookManager {
 
     // #[wasm_bindgen]
     // pub fn get_best_ask_bid(&self) -> (Option<String>, Option<String>) {
-    //     let (best_ask, best_bid) = self.orderbook.borrow().get_best_ask_bid();
+    //     let (best_ask, best_bid) = self.orderbook(None)?.get_best_ask_bid();
     //     (best_ask.map(|x| x.to_string()), best_bid.map(|x| x.to_string()))
     // }
 
+    /// Group the book into buckets of `grouping_size`.
+    /// `rounding` is one of "conservative" (default: asks up, bids down), "floor" or "ceil"
+    /// @returns {Array} [asks: [price: string, quantity: string][], bids: [price: string, quantity: string][]]
     #[wasm_bindgen]
-    pub fn group_prices(&self, grouping_size: String) -> Result<JsValue, JsValue> {
-        let grouping_size_decimal = Decimal::from_str_exact(&grouping_size).map_err(|e| JsValue::from_str(&e.to_string()))?;
-
-        let (grouped_asks, grouped_bids) = self.orderbook.borrow().group_prices(grouping_size_decimal);
-        
-        let asks_js: Vec<_> = grouped_asks
+    pub fn group_prices(&self, grouping_size: String, rounding: Option<String>, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let grouping_size_decimal = Decimal::from_str_exact(&grouping_size).map_err(|e| core_error(e.into()))?;
+        let rounding = match rounding.as_deref() {
+            None | Some("conservative") => GroupingRounding::Conservative,
+            Some("floor") => GroupingRounding::Floor,
+            Some("ceil") => GroupingRounding::Ceil,
+            Some(other) => return Err(JsValue::from_str(&format!("Unknown rounding {}", other))),
+        };
+
+        let (grouped_asks, grouped_bids) = self.orderbook(symbol)?.group_prices_with(grouping_size_decimal, rounding).map_err(core_error)?;
+
+        let asks_js: Vec<(String, String)> = grouped_asks
             .into_iter()
-            .map(|(price, quantity)| {
-                 (price.to_string(), quantity.to_string());
-                // JsValue::from_serde(&tuple).unwrap()
-            })
+            .map(|(price, quantity)| (price.to_string(), quantity.to_string()))
             .collect();
-        let bids_js: Vec<_> = grouped_bids
+        let bids_js: Vec<(String, String)> = grouped_bids
             .into_iter()
-            .map(|(price, quantity)| {
-                (price.to_string(), quantity.to_string());
-                // JsValue::from_serde(&tuple).unwrap()
-            })
+            .map(|(price, quantity)| (price.to_string(), quantity.to_string()))
             .collect();
 
-        // ((JsValue::from_serde(&asks_js).unwrap(), JsValue::from_serde(&bids_js).unwrap()))
         let result = (asks_js, bids_js);
         to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))
     }
 
+    /// Tick aligned grouping sizes (1x, 10x, 100x tick) to list in the UI, empty without a market spec
+    /// @returns {string[]}
+    #[wasm_bindgen]
+    pub fn grouping_presets(&self, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let presets: Vec<String> = self.orderbook(symbol)?.grouping_presets().iter().map(|size| size.to_string()).collect();
+        to_value(&presets).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Cumulative depth for charting, optionally grouped by `grouping_size` and limited to
+    /// `window` percent (1 = 1%) around mid. Values are floats, ready to hand to a chart library
+    /// @returns {{
+    ///   asks: { price: Float64Array, quantity: Float64Array, cumulative_base: Float64Array, cumulative_quote: Float64Array },
+    ///   bids: { price: Float64Array, quantity: Float64Array, cumulative_base: Float64Array, cumulative_quote: Float64Array }
+    /// }}
+    #[wasm_bindgen]
+    pub fn cumulative_depth(&self, grouping_size: Option<String>, window: Option<String>, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let grouping_size_decimal = grouping_size
+            .map(|grouping_size| Decimal::from_str_exact(&grouping_size).map_err(|e| core_error(e.into())))
+            .transpose()?;
+        let window_decimal = window
+            .map(|window| Decimal::from_str_exact(&window).map_err(|e| core_error(e.into())))
+            .transpose()?;
+
+        let curve = self.orderbook(symbol)?.cumulative_depth(grouping_size_decimal, window_decimal).map_err(core_error)?;
+        let result = js_sys::Object::new();
+        js_sys::Reflect::set(&result, &"asks".into(), &depth_points_to_js(&curve.asks)?)?;
+        js_sys::Reflect::set(&result, &"bids".into(), &depth_points_to_js(&curve.bids)?)?;
+        Ok(result.into())
+    }
+
+    /// Export the order book as a versioned JSON snapshot
+    /// @returns {string}
+    #[wasm_bindgen]
+    pub fn export_state(&self, symbol: Option<String>) -> Result<String, JsValue> {
+        let snapshot = self.orderbook(symbol)?.to_snapshot(now_millis());
+        snapshot.to_json().map_err(core_error)
+    }
+
+    /// Export the order book as a compact binary snapshot
+    /// @returns {Uint8Array}
     #[wasm_bindgen]
-    pub fn update_balance(&self, token: String, balance: String) {
-        self.order_manager.borrow_mut().update_balance(token, balance);
+    pub fn export_state_binary(&self, symbol: Option<String>) -> Result<Vec<u8>, JsValue> {
+        self.orderbook(symbol)?.to_snapshot(now_millis()).to_bytes().map_err(core_error)
+    }
+
+    /// Replace the order book with a JSON snapshot produced by `export_state`.
+    /// The book is registered under `symbol` (or the active pair), whatever symbol it was exported from
+    #[wasm_bindgen]
+    pub fn import_state(&self, state: String, symbol: Option<String>) -> Result<(), JsValue> {
+        let snapshot = OrderBookSnapshot::from_json(&state).map_err(core_error)?;
+        self.import_snapshot(snapshot, symbol);
+        Ok(())
+    }
+
+    /// Replace the order book with a binary snapshot produced by `export_state_binary`
+    #[wasm_bindgen]
+    pub fn import_state_binary(&self, state: &[u8], symbol: Option<String>) -> Result<(), JsValue> {
+        let snapshot = OrderBookSnapshot::from_bytes(state).map_err(core_error)?;
+        self.import_snapshot(snapshot, symbol);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn update_balance(&self, token: String, balance: String) -> Result<(), JsValue> {
+        self.order_manager.borrow_mut().update_balance(token, balance)
     }
 
     #[wasm_bindgen]
@@ -167,8 +501,15 @@ impl OrderBookManager {
         maker_fee: String,
         base_token_precision: u32,
 
-    ) {
+    ) -> Result<(), JsValue> {
         //.lock().unwrap();
+        let mut orderbooks = self.orderbooks.borrow_mut();
+        if !orderbooks.contains(&pair_symbol) {
+            // a book filled before any pair was configured belongs to the first pair
+            let mut orderbook = orderbooks.remove("").unwrap_or_else(OrderBook::new);
+            orderbook.symbol = pair_symbol.clone();
+            orderbooks.insert(orderbook);
+        }
         let mut ob = self.order_manager.borrow_mut();
         ob.new_pair_order_compute(pair_symbol, collateral_long_token, collateral_short_token, leverage, max_notional, min_quantity_base, margin_ratio, taker_fee, maker_fee, base_token_precision)
     }
@@ -188,6 +529,10 @@ impl OrderBookManager {
     /// `min_quantity_quote`: String - The minimum quote quantity allowed for the trade.
     /// `slippage`: String - The slippage percentage.
     /// `swap_fee`: String - The swap fee for the trade.
+    ///
+    /// Throws an `Error` whose `code` is `INVALID_INPUT`, `MISSING_CONFIG` or `ARITHMETIC_OVERFLOW`
+    /// when the order cannot be computed. An order the book cannot fill, or a percentage of an
+    /// empty balance, returns zeros
     ///  @returns {{
     ///   cost_long: string,
     ///   cost_short: string,
@@ -211,9 +556,12 @@ impl OrderBookManager {
         is_quote: bool,
         is_buy: bool,
         use_percentage: bool,
-    ) -> Result<JsValue, String> {
+        symbol: Option<String>,
+    ) -> Result<JsValue, JsValue> {
+        let symbol = self.route(symbol);
         self.order_manager.borrow().compute_open_order(
-            &self.orderbook.borrow(),
+            &symbol,
+            &*self.orderbook(Some(symbol.clone()))?,
             pay_token,
             pay_amount,
             limit_price,
@@ -224,13 +572,71 @@ impl OrderBookManager {
         )
     }
 
+    /// `compute_open_order` taking the order as one object instead of positional flags:
+    /// ```js
+    /// {
+    ///   side: "Bid" | "Ask",  // Bid buys (long), Ask sells (short)
+    ///   quantity: { type: "base" | "quote" | "pay_amount" | "percent_of_balance", value: string },
+    ///   order_type: "Market" | "Limit",
+    ///   limit_price?: string,  // required by limit orders
+    /// }
+    /// ```
+    /// `percent_of_balance` is a share of the `pay_token` balance, "0.25" = 25%
+    /// @returns same shape as `compute_open_order`
+    #[wasm_bindgen]
+    pub fn open_order(&self, request: JsValue, pay_token: String, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let request: OpenOrderRequest = serde_wasm_bindgen::from_value(request)
+            .map_err(|e| core_error(CoreError::InvalidInput(format!("Invalid open order request: {}", e))))?;
+        let symbol = self.route(symbol);
+        self.order_manager.borrow().open_order(&symbol, &*self.orderbook(Some(symbol.clone()))?, &request, &pay_token)
+    }
+
+    /// Unrealized PnL, ROE and liquidation figures of a position of `symbol`:
+    /// ```js
+    /// {
+    ///   side: "Bid" | "Ask",  // Bid is a long, Ask a short
+    ///   size: string,
+    ///   entry_price: string,
+    ///   margin: string,
+    ///   leverage: string,
+    /// }
+    /// ```
+    /// Marked at `mark_price`, or at the exit price of closing the whole position at market when omitted
+    /// @returns {{
+    ///   mark_price: string,
+    ///   unrealized_pnl: string,
+    ///   roe: string,
+    ///   margin_ratio: string | undefined,
+    ///   liquidation_price: string,
+    ///   liquidation_distance: string,
+    ///   liquidation_distance_pct: string,
+    /// }}
+    #[wasm_bindgen]
+    pub fn position_metrics(&self, position: JsValue, mark_price: Option<String>, symbol: Option<String>) -> Result<JsValue, JsValue> {
+        let position: Position = serde_wasm_bindgen::from_value(position)
+            .map_err(|e| core_error(CoreError::InvalidInput(format!("Invalid position: {}", e))))?;
+        let symbol = self.route(symbol);
+        let order_compute = self.order_manager.borrow().get_order_compute(&symbol)?;
+        let metrics = match mark_price {
+            Some(mark_price) => {
+                let mark_price_decimal = Decimal::from_str_exact(&mark_price).map_err(|e| core_error(e.into()))?;
+                order_compute.borrow().position_metrics(&position, mark_price_decimal)
+            }
+            None => order_compute.borrow().position_metrics_from_book(&*self.orderbook(Some(symbol))?, &position),
+        }
+        .map_err(core_error)?;
+        to_value(&metrics).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
     #[wasm_bindgen]
     pub fn change_leverage(
         &self,
         new_leverage: String,
-        max_notional: String
-    ) {
+        max_notional: String,
+        symbol: Option<String>,
+    ) -> Result<(), JsValue> {
         self.order_manager.borrow_mut().change_leverage(
+            &self.route(symbol),
             new_leverage,
             max_notional
         )
@@ -241,6 +647,140 @@ impl OrderBookManager {
         self.order_manager.borrow().active_pair_symbol.clone()
     }
 
+    /// Route calls without a symbol to `symbol`, its pair must have been set up with `new_pair_order_compute`
+    #[wasm_bindgen]
+    pub fn set_active_pair(&self, symbol: String) -> Result<(), String> {
+        self.order_manager.borrow_mut().set_active_pair(symbol)
+    }
+
+    /// Register an empty order book for `symbol`
+    #[wasm_bindgen]
+    pub fn create_orderbook(&self, symbol: String) -> Result<(), JsValue> {
+        self.orderbooks.borrow_mut().create(&symbol).map_err(core_error)?;
+        Ok(())
+    }
+
+    /// Drop the order book of `symbol`, returns whether it existed
+    #[wasm_bindgen]
+    pub fn remove_orderbook(&self, symbol: String) -> bool {
+        self.orderbooks.borrow_mut().remove(&symbol).is_some()
+    }
+
+    /// Call `callback(event, symbol)` for every change applied through this manager:
+    /// `{ type: "LevelAdded" | "LevelChanged" | "LevelRemoved", side, price, quantity?, old_quantity? }`,
+    /// `{ type: "BestBidAskChanged", best_ask?, best_bid? }` and `{ type: "SnapshotReset", sequence? }`.
+    /// Decimals are strings
+    /// @param {(event: object, symbol: string) => void} callback
+    /// @returns {number} listener id for `off_change`
+    #[wasm_bindgen]
+    pub fn on_change(&self, callback: js_sys::Function) -> u32 {
+        let id = self.next_listener_id.get();
+        self.next_listener_id.set(id + 1);
+        self.listeners.borrow_mut().push((id, callback));
+        id
+    }
+
+    /// Unregister a listener, returns whether it was registered
+    #[wasm_bindgen]
+    pub fn off_change(&self, listener_id: u32) -> bool {
+        let mut listeners = self.listeners.borrow_mut();
+        let before = listeners.len();
+        listeners.retain(|(id, _)| *id != listener_id);
+        listeners.len() != before
+    }
+
+    /// @returns {string[]} symbols with an order book, sorted
+    #[wasm_bindgen]
+    pub fn list_orderbooks(&self) -> Result<JsValue, JsValue> {
+        to_value(&self.orderbooks.borrow().symbols()).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+}
+
+impl OrderBookManager {
+    // symbol a call is routed to, the active pair when none is given
+    fn route(&self, symbol: Option<String>) -> String {
+        symbol.unwrap_or_else(|| self.order_manager.borrow().active_pair_symbol.clone())
+    }
+
+    fn orderbook(&self, symbol: Option<String>) -> Result<Ref<'_, OrderBook>, String> {
+        let symbol = self.route(symbol);
+        Ref::filter_map(self.orderbooks.borrow(), |orderbooks| orderbooks.get(&symbol))
+            .map_err(|_| format!("Order book {} not found", symbol))
+    }
+
+    // Feeds may start before the book is registered, writes create it.
+    // The changes are dispatched once the book is released, so listeners can query it
+    fn write_orderbook<R>(&self, symbol: Option<String>, write: impl FnOnce(&mut OrderBook) -> R) -> R {
+        let symbol = self.route(symbol);
+        let (result, events) = {
+            let mut orderbooks = self.orderbooks.borrow_mut();
+            let orderbook = orderbooks.get_or_create(&symbol);
+            orderbook.set_record_events(true);
+            let result = write(orderbook);
+            (result, orderbook.drain_events())
+        };
+        self.dispatch_events(&symbol, events);
+        result
+    }
+
+    fn dispatch_events(&self, symbol: &str, events: Vec<BookEvent>) {
+        // listeners may (un)register from a callback, call a copy
+        let listeners: Vec<js_sys::Function> = self.listeners.borrow().iter().map(|(_, callback)| callback.clone()).collect();
+        if listeners.is_empty() {
+            return;
+        }
+        let symbol = JsValue::from_str(symbol);
+        for event in events {
+            let event = match to_value(&event) {
+                Ok(event) => event,
+                Err(e) => {
+                    log(format!("RUST:: cannot serialize book event: {}", e).as_str());
+                    continue;
+                }
+            };
+            for callback in &listeners {
+                if let Err(e) = callback.call2(&JsValue::NULL, &event, &symbol) {
+                    log(format!("RUST:: book event listener failed: {:?}", e).as_str());
+                }
+            }
+        }
+    }
+
+    fn record(&self, symbol: &Option<String>, message: impl FnOnce() -> ReplayMessage) {
+        if let Some(recorder) = self.recorder.borrow_mut().as_mut() {
+            let symbol = self.route(symbol.clone());
+            if let Err(e) = recorder.record(now_millis(), &symbol, message()) {
+                log(format!("RUST:: cannot record orderbook {}: {}", symbol, e).as_str());
+            }
+        }
+    }
+
+    fn import_snapshot(&self, snapshot: OrderBookSnapshot, symbol: Option<String>) {
+        self.write_orderbook(symbol, |orderbook| orderbook.initialize(snapshot.asks, snapshot.bids, snapshot.sequence));
+    }
+}
+
+// Levels of one side as a replay message
+fn side_update(is_ask: bool, levels: Vec<PriceLevel>) -> ReplayMessage {
+    if is_ask {
+        ReplayMessage::Update { asks: levels, bids: vec![] }
+    } else {
+        ReplayMessage::Update { asks: vec![], bids: levels }
+    }
+}
+
+// A core error as a JS `Error` whose `code` property is the stable `CoreError::code`
+// Wall clock in milliseconds for snapshot and recording timestamps
+fn now_millis() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+pub(crate) fn core_error(error: CoreError) -> JsValue {
+    let js_error = js_sys::Error::new(error.message());
+    // setting a property on a fresh object cannot fail
+    let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("code"), &JsValue::from_str(error.code()));
+    js_error.into()
 }
 
 // #[wasm_bindgen]
@@ -252,7 +792,7 @@ impl OrderBookManager {
 // }
 
 
-fn to_price_level_vec(arr: &Array) -> Vec<(String, String)> {
+fn to_price_level_vec(arr: &Array) -> Result<Vec<(String, String)>, JsValue> {
     let mut result = Vec::new();
     for i in 0..arr.length() {
         let tuple = arr.get(i);
@@ -261,26 +801,44 @@ fn to_price_level_vec(arr: &Array) -> Vec<(String, String)> {
             if tuple.length() == 0 {
                 continue;
             }
-            let price = tuple.get(0).as_string().expect("[to price level vec] invalid price");
-            let quantity = tuple.get(1).as_string().expect("[to price level vec] invalid quantity");
+            let price = level_field(&tuple, 0, "price")?;
+            let quantity = level_field(&tuple, 1, "quantity")?;
             result.push((price, quantity));
         }
     }
-    result
+    Ok(result)
 }
 
+// numbers cross as strings to keep their decimals, anything else is an `INVALID_INPUT` error
+fn level_field(tuple: &Array, index: u32, name: &str) -> Result<String, JsValue> {
+    let value = tuple.get(index);
+    value.as_string().ok_or_else(|| {
+        core_error(CoreError::InvalidInput(format!("Invalid {}, expected a string, got {:?}", name, value)))
+    })
+}
 
-fn to_vec_tuple(arr: &Array) -> Vec<(String, String)> {
-    let mut result = Vec::new();
-    for i in 0..arr.length() {
-        let tuple = arr.get(i);
-        if let Some(tuple) = tuple.dyn_into::<Array>().ok() {
-            let price = tuple.get(0).as_string().unwrap();
-            let quantity = tuple.get(1).as_string().unwrap();
-            result.push((price, quantity));
-        }
-    }
-    result
+// [price, quantity] string pairs as levels, a non-string or unparsable number is an `INVALID_INPUT` error
+fn parse_price_levels(arr: &Array) -> Result<Vec<PriceLevel>, JsValue> {
+    to_price_level_vec(arr)?
+        .into_iter()
+        .map(|(price, quantity)| Ok((Decimal::from_str_exact(&price)?, Decimal::from_str_exact(&quantity)?)))
+        .collect::<Result<Vec<PriceLevel>, rust_decimal::Error>>()
+        .map_err(|e| core_error(e.into()))
 }
+
     
 
+
+// one Float64Array per column, so charts can consume the curve without per point objects
+fn depth_points_to_js(points: &[DepthPoint]) -> Result<JsValue, JsValue> {
+    let column = |value: fn(&DepthPoint) -> Decimal| -> js_sys::Float64Array {
+        let values: Vec<f64> = points.iter().map(|point| value(point).to_f64().unwrap_or(f64::NAN)).collect();
+        js_sys::Float64Array::from(&values[..])
+    };
+    let side = js_sys::Object::new();
+    js_sys::Reflect::set(&side, &"price".into(), &column(|point| point.price))?;
+    js_sys::Reflect::set(&side, &"quantity".into(), &column(|point| point.quantity))?;
+    js_sys::Reflect::set(&side, &"cumulative_base".into(), &column(|point| point.cumulative_base))?;
+    js_sys::Reflect::set(&side, &"cumulative_quote".into(), &column(|point| point.cumulative_quote))?;
+    Ok(side.into())
+}
@@ -1,9 +1,11 @@
-use core::{compute::order::{self, FuturesOrder}, orderbook::OrderBook};
+use core::{compute::order, error::CoreResult, orderbook::OrderBook};
 use std::{collections::HashMap, sync::{Arc, Mutex}, future::Future, cell::RefCell, rc::Rc};
 
 use rust_decimal::Decimal;
 use wasm_bindgen::prelude::*;
 
+use crate::core_error;
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -39,36 +41,39 @@ impl OrderManager {
             taker_fee: String,
             maker_fee: String,
             base_token_precision: u32,
-    ) {
+    ) -> Result<(), JsValue> {
         log(format!("RUST:: new pair {}", pair_symbol.clone()).as_str());
+        let order_compute = order::FuturesOrderCalculation::new(
+            collateral_long_token,
+            collateral_short_token,
+            leverage,
+            max_notional,
+            min_quantity_base,
+            margin_ratio,
+            taker_fee,
+            maker_fee,
+            base_token_precision,
+        ).map_err(core_error)?;
         self.pair_order_compute.insert(
             pair_symbol.clone(),
-            Rc::new(
-            RefCell::new(
-                order::FuturesOrderCalculation::new(
-                    collateral_long_token,
-                    collateral_short_token,
-                    leverage,
-                    max_notional,
-                    min_quantity_base,
-                    margin_ratio,
-                    taker_fee,
-                    maker_fee,
-                    base_token_precision,
-                )
-            ))
+            Rc::new(RefCell::new(order_compute))
         );
         log(format!("RUST:: new pair {} DONE 1", pair_symbol.clone()).as_str());
         self.active_pair_symbol = pair_symbol.clone();
         log(format!("RUST:: new pair {} DONE 2", pair_symbol.clone()).as_str());
+        Ok(())
     }
 
-    pub fn update_balance(&mut self, token: String, balance: String) {
-        self.user_balance.insert(token, Decimal::from_str_exact(&balance).unwrap());
+    pub fn update_balance(&mut self, token: String, balance: String) -> Result<(), JsValue> {
+        let balance = order::string_to_decimal(&balance, "Invalid balance").map_err(core_error)?;
+        self.user_balance.insert(token, balance);
+        Ok(())
     }
 
+    /// Compute an open order for `symbol` with that pair's calculation against its `orderbook`
     pub fn compute_open_order(
         &self,
+        symbol: &str,
         orderbook: &OrderBook,
         pay_token: String,
         pay_amount: String,
@@ -77,14 +82,14 @@ impl OrderManager {
         is_quote: bool,
         is_buy: bool,
         use_percentage: bool,
-    ) -> Result<JsValue, String> {
+    ) -> Result<JsValue, JsValue> {
 
         log(format!("RUST:: Compute open order: {:?}, is_quote {}, is_buy {}", quantity, is_quote, is_buy).as_str());
         let order_type: order::OrderType;
         let mut price: Option<Decimal> = None;
         match limit_price {
             Some(expr) => {
-                price = Some(Decimal::from_str_exact(&expr).unwrap());
+                price = Some(order::string_to_decimal(&expr, "Invalid limit price").map_err(core_error)?);
                 order_type = order::OrderType::Limit;
                 log("RUST:: Limit price");
             },
@@ -94,44 +99,74 @@ impl OrderManager {
             },
         }
         log(format!("RUST:: order type: {}", price.unwrap_or_else(||Decimal::ZERO)).as_str());
-        let result = self.get_active_order_compute().borrow_mut().compute_open_order(
+        let result = self.get_order_compute(symbol)?.borrow_mut().compute_open_order(
             order_type,
             orderbook,
-            *self.user_balance.get(&pay_token).clone().unwrap_or_else(|| &Decimal::ZERO),
-            order::string_to_decimal(&pay_amount, "Invlaid pay amount"),
-            order::string_to_decimal(&quantity, "Invalid quantity"),
+            self.balance(&pay_token),
+            order::string_to_decimal(&pay_amount, "Invalid pay amount").map_err(core_error)?,
+            order::string_to_decimal(&quantity, "Invalid quantity").map_err(core_error)?,
             price,
             is_quote,
             is_buy,
             use_percentage
         );
-        let final_result = match result {
-            Ok(result) => result,
-            Err(..) => {
-                log("RUST:: compute error");
-                FuturesOrder::empty()
-            },
-        };
-        log(format!("RUST:: raw result {:?}", &final_result).as_str());
+        order_to_js(result)
+    }
 
-         Ok(serde_wasm_bindgen::to_value(&final_result).unwrap())
+    /// Compute `request` for `symbol` against its `orderbook`, using the balance of `pay_token` as margin
+    pub fn open_order(
+        &self,
+        symbol: &str,
+        orderbook: &OrderBook,
+        request: &order::OpenOrderRequest,
+        pay_token: &str,
+    ) -> Result<JsValue, JsValue> {
+        log(format!("RUST:: Open order: {:?}", request).as_str());
+        let result = self.get_order_compute(symbol)?.borrow().open_order(orderbook, request, self.balance(pay_token));
+        order_to_js(result)
     }
 
     pub fn change_leverage(
         &self,
+        symbol: &str,
         new_leverage: String,
         max_notional: String,
-    ) {
-        log(format!("change leverage to {}, max notional {}", new_leverage, max_notional).as_str());
-        self.get_active_order_compute().borrow_mut()
-            .change_leverage(Decimal::from_str_exact(&new_leverage).unwrap(), max_notional);
-        let leverage_after = self.get_active_order_compute().borrow().leverage;
+    ) -> Result<(), JsValue> {
+        log(format!("change leverage of {} to {}, max notional {}", symbol, new_leverage, max_notional).as_str());
+        let order_compute = self.get_order_compute(symbol)?;
+        let new_leverage = order::string_to_decimal(&new_leverage, "Invalid leverage").map_err(core_error)?;
+        order_compute.borrow_mut()
+            .change_leverage(new_leverage, max_notional)
+            .map_err(core_error)?;
+        let leverage_after = order_compute.borrow().leverage;
         log(format!("change leverage to {} after", leverage_after).as_str());
+        Ok(())
     }
 
-    pub fn get_active_order_compute(&self) -> OrderCalculatationLockable {
-        log(format!("RUST:: active pair {}", self.active_pair_symbol.clone()).as_str());
-        return self.pair_order_compute.get(&self.active_pair_symbol.clone()).expect("Not initialized. Make sure you have set active pair, and it's configuration.").clone();
+    pub fn set_active_pair(&mut self, symbol: String) -> Result<(), String> {
+        self.get_order_compute(&symbol)?;
+        self.active_pair_symbol = symbol;
+        Ok(())
+    }
+
+    fn balance(&self, token: &str) -> Decimal {
+        self.user_balance.get(token).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    pub fn get_order_compute(&self, symbol: &str) -> Result<OrderCalculatationLockable, String> {
+        self.pair_order_compute
+            .get(symbol)
+            .cloned()
+            .ok_or_else(|| format!("Pair {} not initialized. Make sure you have set its configuration.", symbol))
     }
 }
 
+fn order_to_js(result: CoreResult<order::FuturesOrder>) -> Result<JsValue, JsValue> {
+    let final_result = result.map_err(|e| {
+        log(format!("RUST:: compute error {}: {}", e.code(), e).as_str());
+        core_error(e)
+    })?;
+    log(format!("RUST:: raw result {:?}", &final_result).as_str());
+
+    serde_wasm_bindgen::to_value(&final_result).map_err(|e| JsValue::from_str(&e.to_string()))
+}